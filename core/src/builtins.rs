@@ -1,6 +1,56 @@
 use crate::*;
 use core::fmt::Write;
 
+/// Define a builtin `( a b -- c )`, where `c` is computed from `a` and `b`.
+/// The runtime may also be named, for words that push a flag
+macro_rules! binop {
+    ($name:ident, |$ctxt:ident, $a:ident, $b:ident| $body:expr) => {
+        pub fn $name<BuiltinTok, SeqTok, Sdata, Sexec, O>(
+            $ctxt: &mut Runtime<BuiltinTok, SeqTok, Sdata, Sexec, O>,
+        ) -> Result<(), Error>
+        where
+            Sdata: Stack<Item = i32>,
+            Sexec: ExecutionStack<BuiltinTok, SeqTok>,
+            SeqTok: Clone,
+            BuiltinTok: Clone,
+            O: Write,
+        {
+            let [$a, $b] = $ctxt.data_stk.pop2()?;
+            let res = $body;
+            $ctxt.data_stk.push(res)
+        }
+    };
+    ($name:ident, |$a:ident, $b:ident| $body:expr) => {
+        binop!($name, |ctxt, $a, $b| $body);
+    };
+}
+
+/// The number of data stack items consumed and produced by the builtin with
+/// the given (registered) name. Returns `None` for unknown builtins, and for
+/// those whose effect depends on the values or depth of the stack, such as
+/// `pick` or `sum`
+pub fn arity(name: &str) -> Option<(usize, usize)> {
+    Some(match name {
+        "col" | "cell-bits" | "depth" | "i" | "j" | "key" | "max-int" | "min-int" | "r>"
+        | "PRIV_LOOP" => (0, 1),
+        "2r>" | "2r@" => (0, 2),
+        ".s" | "cr" | "decimal" | "exit" | "hex" | "r>drop" | "rdrop" | "unloop" | "words" => {
+            (0, 0)
+        }
+        "." | ">r" | "allot" | "drop" | "emit" | "spaces" | "tab-to" | "u." => (1, 0),
+        "@" | "alpha?" | "cells" | "digit?" | "invert" | "space?" | "PRIV_PLUS_LOOP" => (1, 1),
+        "2@" | "dup" => (1, 2),
+        "!" | "+!" | "2>r" => (2, 0),
+        "2!" => (3, 0),
+        "*" | "+" | "-" | "/" | "<" | "=" | ">" | "and" | "bit-clr" | "bit-set" | "bit-test"
+        | "lshift" | "mod" | "or" | "rshift" | "xor" => (2, 1),
+        "/mod" | "swap" => (2, 2),
+        "2dup" => (2, 4),
+        "rot" => (3, 3),
+        _ => return None,
+    })
+}
+
 pub fn bi_emit<BuiltinTok, SeqTok, Sdata, Sexec, O>(
     ctxt: &mut Runtime<BuiltinTok, SeqTok, Sdata, Sexec, O>,
 ) -> Result<(), Error>
@@ -11,12 +61,431 @@ where
     BuiltinTok: Clone,
     O: Write,
 {
-    let word = ctxt.data_stk.pop()? as u32;
-    let symbol = core::char::from_u32(word).unwrap_or('‽');
-    write!(&mut ctxt.cur_output, "{}", symbol).map_err(|_| Error::OutputFormat)
+    let word = ctxt.data_stk.pop()? as u32;
+    let symbol = match ctxt.emit_mode {
+        EmitMode::Lossy => core::char::from_u32(word).unwrap_or('‽'),
+        EmitMode::Strict => core::char::from_u32(word).ok_or(Error::BadMath)?,
+        EmitMode::RawByte => char::from(word as u8),
+    };
+    write!(ctxt.output(), "{}", symbol).map_err(|_| Error::OutputFormat)
+}
+
+/// Print the top of the stack as a signed number in decimal. In any other
+/// base, the cell's bit pattern is printed, so `-1` in hex is `ffffffff`.
+///
+/// Digits are produced by `core::fmt`, which handles `i32::MIN` correctly,
+/// rather than by negating the value, which would overflow.
+pub fn bi_pop<BuiltinTok, SeqTok, Sdata, Sexec, O>(
+    ctxt: &mut Runtime<BuiltinTok, SeqTok, Sdata, Sexec, O>,
+) -> Result<(), Error>
+where
+    Sdata: Stack<Item = i32>,
+    Sexec: ExecutionStack<BuiltinTok, SeqTok>,
+    SeqTok: Clone,
+    BuiltinTok: Clone,
+    O: Write,
+{
+    if ctxt.base != 10 {
+        return bi_u_dot(ctxt);
+    }
+    let val = ctxt.data_stk.pop()?;
+    let newline = ctxt.newline;
+    write!(ctxt.output(), "{}{}", val, newline.as_str())?;
+    Ok(())
+}
+
+/// Print the top of the stack as an unsigned number in the current base, so
+/// `-1 u.` prints `4294967295` in decimal
+pub fn bi_u_dot<BuiltinTok, SeqTok, Sdata, Sexec, O>(
+    ctxt: &mut Runtime<BuiltinTok, SeqTok, Sdata, Sexec, O>,
+) -> Result<(), Error>
+where
+    Sdata: Stack<Item = i32>,
+    Sexec: ExecutionStack<BuiltinTok, SeqTok>,
+    SeqTok: Clone,
+    BuiltinTok: Clone,
+    O: Write,
+{
+    let base = ctxt.base;
+    if !(2..=36).contains(&base) {
+        return Err(Error::BadMath);
+    }
+
+    let mut rest = ctxt.data_stk.pop()? as u32;
+    let mut digits = [0u8; 32];
+    let mut start = digits.len();
+    loop {
+        start -= 1;
+        digits[start] = b"0123456789abcdefghijklmnopqrstuvwxyz"[(rest % base) as usize];
+        rest /= base;
+        if rest == 0 {
+            break;
+        }
+    }
+
+    let newline = ctxt.newline;
+    let mut out = ctxt.output();
+    for digit in &digits[start..] {
+        out.write_char(char::from(*digit))?;
+    }
+    out.write_str(newline.as_str())?;
+    Ok(())
+}
+
+/// Print numbers in base 16 from now on
+pub fn bi_hex<BuiltinTok, SeqTok, Sdata, Sexec, O>(
+    ctxt: &mut Runtime<BuiltinTok, SeqTok, Sdata, Sexec, O>,
+) -> Result<(), Error>
+where
+    Sdata: Stack<Item = i32>,
+    Sexec: ExecutionStack<BuiltinTok, SeqTok>,
+    SeqTok: Clone,
+    BuiltinTok: Clone,
+    O: Write,
+{
+    ctxt.base = 16;
+    Ok(())
+}
+
+/// Print numbers in base 10 from now on, the default
+pub fn bi_decimal<BuiltinTok, SeqTok, Sdata, Sexec, O>(
+    ctxt: &mut Runtime<BuiltinTok, SeqTok, Sdata, Sexec, O>,
+) -> Result<(), Error>
+where
+    Sdata: Stack<Item = i32>,
+    Sexec: ExecutionStack<BuiltinTok, SeqTok>,
+    SeqTok: Clone,
+    BuiltinTok: Clone,
+    O: Write,
+{
+    ctxt.base = 10;
+    Ok(())
+}
+
+/// Print the whole data stack as `<n> a b c `, bottom first, without
+/// consuming anything
+pub fn bi_dot_s<BuiltinTok, SeqTok, Sdata, Sexec, O>(
+    ctxt: &mut Runtime<BuiltinTok, SeqTok, Sdata, Sexec, O>,
+) -> Result<(), Error>
+where
+    Sdata: Stack<Item = i32>,
+    Sexec: ExecutionStack<BuiltinTok, SeqTok>,
+    SeqTok: Clone,
+    BuiltinTok: Clone,
+    O: Write,
+{
+    let depth = ctxt.data_stk.len();
+    write!(ctxt.output(), "<{}> ", depth)?;
+    for idx in 0..depth {
+        let val = ctxt.data_stk.as_slice()[idx];
+        write!(ctxt.output(), "{} ", val)?;
+    }
+    Ok(())
+}
+
+pub fn bi_drop<BuiltinTok, SeqTok, Sdata, Sexec, O>(
+    ctxt: &mut Runtime<BuiltinTok, SeqTok, Sdata, Sexec, O>,
+) -> Result<(), Error>
+where
+    Sdata: Stack<Item = i32>,
+    Sexec: ExecutionStack<BuiltinTok, SeqTok>,
+    SeqTok: Clone,
+    BuiltinTok: Clone,
+    O: Write,
+{
+    let _ = ctxt.data_stk.pop()?;
+    Ok(())
+}
+
+pub fn bi_rot<BuiltinTok, SeqTok, Sdata, Sexec, O>(
+    ctxt: &mut Runtime<BuiltinTok, SeqTok, Sdata, Sexec, O>,
+) -> Result<(), Error>
+where
+    Sdata: Stack<Item = i32>,
+    Sexec: ExecutionStack<BuiltinTok, SeqTok>,
+    SeqTok: Clone,
+    BuiltinTok: Clone,
+    O: Write,
+{
+    let [bot, mid, top] = ctxt.data_stk.pop3()?;
+
+    ctxt.data_stk.push(mid)?;
+    ctxt.data_stk.push(top)?;
+    ctxt.data_stk.push(bot)?;
+
+    Ok(())
+}
+
+pub fn bi_cr<BuiltinTok, SeqTok, Sdata, Sexec, O>(
+    ctxt: &mut Runtime<BuiltinTok, SeqTok, Sdata, Sexec, O>,
+) -> Result<(), Error>
+where
+    Sdata: Stack<Item = i32>,
+    Sexec: ExecutionStack<BuiltinTok, SeqTok>,
+    SeqTok: Clone,
+    BuiltinTok: Clone,
+    O: Write,
+{
+    let newline = ctxt.newline;
+    ctxt.output().write_str(newline.as_str())?;
+    Ok(())
+}
+
+/// Push the current output column, `( -- n )`
+pub fn bi_col<BuiltinTok, SeqTok, Sdata, Sexec, O>(
+    ctxt: &mut Runtime<BuiltinTok, SeqTok, Sdata, Sexec, O>,
+) -> Result<(), Error>
+where
+    Sdata: Stack<Item = i32>,
+    Sexec: ExecutionStack<BuiltinTok, SeqTok>,
+    SeqTok: Clone,
+    BuiltinTok: Clone,
+    O: Write,
+{
+    let col = i32::try_from(ctxt.column()).map_err(|_| Error::BadMath)?;
+    ctxt.data_stk.push(col)
+}
+
+/// Print `n` spaces, `( n -- )`. Nothing is printed if `n` is negative
+pub fn bi_spaces<BuiltinTok, SeqTok, Sdata, Sexec, O>(
+    ctxt: &mut Runtime<BuiltinTok, SeqTok, Sdata, Sexec, O>,
+) -> Result<(), Error>
+where
+    Sdata: Stack<Item = i32>,
+    Sexec: ExecutionStack<BuiltinTok, SeqTok>,
+    SeqTok: Clone,
+    BuiltinTok: Clone,
+    O: Write,
+{
+    let count = ctxt.data_stk.pop()?;
+    for _ in 0..count {
+        ctxt.output().write_char(' ')?;
+    }
+    Ok(())
+}
+
+/// Print spaces until the output reaches column `n`, `( n -- )`. Nothing is
+/// printed if the output is already at or past that column
+pub fn bi_tab_to<BuiltinTok, SeqTok, Sdata, Sexec, O>(
+    ctxt: &mut Runtime<BuiltinTok, SeqTok, Sdata, Sexec, O>,
+) -> Result<(), Error>
+where
+    Sdata: Stack<Item = i32>,
+    Sexec: ExecutionStack<BuiltinTok, SeqTok>,
+    SeqTok: Clone,
+    BuiltinTok: Clone,
+    O: Write,
+{
+    let target = ctxt.data_stk.pop()?;
+    while (ctxt.column() as i64) < i64::from(target) {
+        ctxt.output().write_char(' ')?;
+    }
+    Ok(())
+}
+
+/// Unwind all stacks, and stop execution with `Error::Abort`
+pub fn bi_abort<BuiltinTok, SeqTok, Sdata, Sexec, O>(
+    ctxt: &mut Runtime<BuiltinTok, SeqTok, Sdata, Sexec, O>,
+) -> Result<(), Error>
+where
+    Sdata: Stack<Item = i32>,
+    Sexec: ExecutionStack<BuiltinTok, SeqTok>,
+    SeqTok: Clone,
+    BuiltinTok: Clone,
+    O: Write,
+{
+    ctxt.unwind();
+    Err(Error::Abort)
+}
+
+/// Return early from the word being executed
+pub fn bi_exit<BuiltinTok, SeqTok, Sdata, Sexec, O>(
+    ctxt: &mut Runtime<BuiltinTok, SeqTok, Sdata, Sexec, O>,
+) -> Result<(), Error>
+where
+    Sdata: Stack<Item = i32>,
+    Sexec: ExecutionStack<BuiltinTok, SeqTok>,
+    SeqTok: Clone,
+    BuiltinTok: Clone,
+    O: Write,
+{
+    ctxt.flow_stk.pop()?;
+    Ok(())
+}
+
+/// Discard the index and limit of the innermost `do` loop from the return
+/// stack, which must be done before using `exit` within a loop
+pub fn bi_unloop<BuiltinTok, SeqTok, Sdata, Sexec, O>(
+    ctxt: &mut Runtime<BuiltinTok, SeqTok, Sdata, Sexec, O>,
+) -> Result<(), Error>
+where
+    Sdata: Stack<Item = i32>,
+    Sexec: ExecutionStack<BuiltinTok, SeqTok>,
+    SeqTok: Clone,
+    BuiltinTok: Clone,
+    O: Write,
+{
+    ctxt.ret_stk.pop2()?;
+    Ok(())
+}
+
+/// Stop execution, clearing the flow and return stacks but keeping the data
+/// stack, as with `quit` in standard Forth
+pub fn bi_quit<BuiltinTok, SeqTok, Sdata, Sexec, O>(
+    ctxt: &mut Runtime<BuiltinTok, SeqTok, Sdata, Sexec, O>,
+) -> Result<(), Error>
+where
+    Sdata: Stack<Item = i32>,
+    Sexec: ExecutionStack<BuiltinTok, SeqTok>,
+    SeqTok: Clone,
+    BuiltinTok: Clone,
+    O: Write,
+{
+    ctxt.quit();
+    Ok(())
+}
+
+/// Read a single character of input. If none has been given, this yields
+/// `StepResult::NeedInput` until the host provides one
+pub fn bi_key<BuiltinTok, SeqTok, Sdata, Sexec, O>(
+    ctxt: &mut Runtime<BuiltinTok, SeqTok, Sdata, Sexec, O>,
+) -> Result<(), Error>
+where
+    Sdata: Stack<Item = i32>,
+    Sexec: ExecutionStack<BuiltinTok, SeqTok>,
+    SeqTok: Clone,
+    BuiltinTok: Clone,
+    O: Write,
+{
+    match ctxt.next_key.take() {
+        Some(ch) => ctxt.data_stk.push(ch)?,
+        None => ctxt.awaiting_input = true,
+    }
+    Ok(())
+}
+
+/// Push the number of items on the data stack, not counting the result
+pub fn bi_depth<BuiltinTok, SeqTok, Sdata, Sexec, O>(
+    ctxt: &mut Runtime<BuiltinTok, SeqTok, Sdata, Sexec, O>,
+) -> Result<(), Error>
+where
+    Sdata: Stack<Item = i32>,
+    Sexec: ExecutionStack<BuiltinTok, SeqTok>,
+    SeqTok: Clone,
+    BuiltinTok: Clone,
+    O: Write,
+{
+    let depth = i32::try_from(ctxt.data_stk.len()).map_err(|_| Error::BadMath)?;
+    ctxt.data_stk.push(depth)
+}
+
+/// Push the width of a cell, in bits
+pub fn bi_cell_bits<BuiltinTok, SeqTok, Sdata, Sexec, O>(
+    ctxt: &mut Runtime<BuiltinTok, SeqTok, Sdata, Sexec, O>,
+) -> Result<(), Error>
+where
+    Sdata: Stack<Item = i32>,
+    Sexec: ExecutionStack<BuiltinTok, SeqTok>,
+    SeqTok: Clone,
+    BuiltinTok: Clone,
+    O: Write,
+{
+    ctxt.data_stk.push(i32::BITS as i32)
+}
+
+/// Push the largest value a cell can hold
+pub fn bi_max_int<BuiltinTok, SeqTok, Sdata, Sexec, O>(
+    ctxt: &mut Runtime<BuiltinTok, SeqTok, Sdata, Sexec, O>,
+) -> Result<(), Error>
+where
+    Sdata: Stack<Item = i32>,
+    Sexec: ExecutionStack<BuiltinTok, SeqTok>,
+    SeqTok: Clone,
+    BuiltinTok: Clone,
+    O: Write,
+{
+    ctxt.data_stk.push(i32::MAX)
+}
+
+/// Push the smallest value a cell can hold
+pub fn bi_min_int<BuiltinTok, SeqTok, Sdata, Sexec, O>(
+    ctxt: &mut Runtime<BuiltinTok, SeqTok, Sdata, Sexec, O>,
+) -> Result<(), Error>
+where
+    Sdata: Stack<Item = i32>,
+    Sexec: ExecutionStack<BuiltinTok, SeqTok>,
+    SeqTok: Clone,
+    BuiltinTok: Clone,
+    O: Write,
+{
+    ctxt.data_stk.push(i32::MIN)
+}
+
+binop!(bi_lt, |ctxt, a, b| ctxt.flag(a < b));
+
+binop!(bi_gt, |ctxt, a, b| ctxt.flag(a > b));
+
+pub fn bi_retstk_push<BuiltinTok, SeqTok, Sdata, Sexec, O>(
+    ctxt: &mut Runtime<BuiltinTok, SeqTok, Sdata, Sexec, O>,
+) -> Result<(), Error>
+where
+    Sdata: Stack<Item = i32>,
+    Sexec: ExecutionStack<BuiltinTok, SeqTok>,
+    SeqTok: Clone,
+    BuiltinTok: Clone,
+    O: Write,
+{
+    let val = ctxt.data_stk.pop()?;
+    ctxt.ret_stk.push(val)?;
+    Ok(())
+}
+
+pub fn bi_retstk_pop<BuiltinTok, SeqTok, Sdata, Sexec, O>(
+    ctxt: &mut Runtime<BuiltinTok, SeqTok, Sdata, Sexec, O>,
+) -> Result<(), Error>
+where
+    Sdata: Stack<Item = i32>,
+    Sexec: ExecutionStack<BuiltinTok, SeqTok>,
+    SeqTok: Clone,
+    BuiltinTok: Clone,
+    O: Write,
+{
+    let val = ctxt.ret_stk.pop()?;
+    ctxt.data_stk.push(val)?;
+    Ok(())
+}
+
+pub fn bi_retstk_drop<BuiltinTok, SeqTok, Sdata, Sexec, O>(
+    ctxt: &mut Runtime<BuiltinTok, SeqTok, Sdata, Sexec, O>,
+) -> Result<(), Error>
+where
+    Sdata: Stack<Item = i32>,
+    Sexec: ExecutionStack<BuiltinTok, SeqTok>,
+    SeqTok: Clone,
+    BuiltinTok: Clone,
+    O: Write,
+{
+    ctxt.ret_stk.pop()?;
+    Ok(())
+}
+
+pub fn bi_retstk_2push<BuiltinTok, SeqTok, Sdata, Sexec, O>(
+    ctxt: &mut Runtime<BuiltinTok, SeqTok, Sdata, Sexec, O>,
+) -> Result<(), Error>
+where
+    Sdata: Stack<Item = i32>,
+    Sexec: ExecutionStack<BuiltinTok, SeqTok>,
+    SeqTok: Clone,
+    BuiltinTok: Clone,
+    O: Write,
+{
+    let [bot, top] = ctxt.data_stk.pop2()?;
+    ctxt.ret_stk.push(bot)?;
+    ctxt.ret_stk.push(top)?;
+    Ok(())
 }
 
-pub fn bi_pop<BuiltinTok, SeqTok, Sdata, Sexec, O>(
+pub fn bi_retstk_2pop<BuiltinTok, SeqTok, Sdata, Sexec, O>(
     ctxt: &mut Runtime<BuiltinTok, SeqTok, Sdata, Sexec, O>,
 ) -> Result<(), Error>
 where
@@ -26,11 +495,13 @@ where
     BuiltinTok: Clone,
     O: Write,
 {
-    writeln!(&mut ctxt.cur_output, "{}", ctxt.data_stk.pop()?)?;
+    let [bot, top] = ctxt.ret_stk.pop2()?;
+    ctxt.data_stk.push(bot)?;
+    ctxt.data_stk.push(top)?;
     Ok(())
 }
 
-pub fn bi_drop<BuiltinTok, SeqTok, Sdata, Sexec, O>(
+pub fn bi_retstk_2fetch<BuiltinTok, SeqTok, Sdata, Sexec, O>(
     ctxt: &mut Runtime<BuiltinTok, SeqTok, Sdata, Sexec, O>,
 ) -> Result<(), Error>
 where
@@ -40,11 +511,29 @@ where
     BuiltinTok: Clone,
     O: Write,
 {
-    let _ = ctxt.data_stk.pop()?;
+    let top = *ctxt.ret_stk.peek_back(0)?;
+    let bot = *ctxt.ret_stk.peek_back(1)?;
+    ctxt.data_stk.push(bot)?;
+    ctxt.data_stk.push(top)?;
     Ok(())
 }
 
-pub fn bi_rot<BuiltinTok, SeqTok, Sdata, Sexec, O>(
+binop!(bi_eq, |ctxt, a, b| ctxt.flag(a == b));
+
+binop!(bi_add, |a, b| a.wrapping_add(b));
+
+binop!(bi_sub, |a, b| a.wrapping_sub(b));
+
+binop!(bi_mul, |a, b| a.wrapping_mul(b));
+
+binop!(bi_and, |a, b| a & b);
+
+binop!(bi_or, |a, b| a | b);
+
+binop!(bi_xor, |a, b| a ^ b);
+
+/// Flip every bit, so `0 invert` gives `-1` (the default true flag)
+pub fn bi_invert<BuiltinTok, SeqTok, Sdata, Sexec, O>(
     ctxt: &mut Runtime<BuiltinTok, SeqTok, Sdata, Sexec, O>,
 ) -> Result<(), Error>
 where
@@ -54,18 +543,33 @@ where
     BuiltinTok: Clone,
     O: Write,
 {
-    let top = ctxt.data_stk.pop()?;
-    let mid = ctxt.data_stk.pop()?;
-    let bot = ctxt.data_stk.pop()?;
-
-    ctxt.data_stk.push(mid)?;
-    ctxt.data_stk.push(top)?;
-    ctxt.data_stk.push(bot)?;
+    let val = ctxt.data_stk.pop()?;
+    ctxt.data_stk.push(!val)
+}
 
+/// Fail on a zero divisor, rather than panicking
+fn check_divisor(divisor: i32) -> Result<(), Error> {
+    if divisor == 0 {
+        return Err(Error::DivideByZero);
+    }
     Ok(())
 }
 
-pub fn bi_cr<BuiltinTok, SeqTok, Sdata, Sexec, O>(
+// Division is truncated (towards zero), so a remainder takes the sign of the
+// dividend: `-7 2 /mod` gives `-1 -3`, rather than the `1 -4` of floored
+// division. `min-int -1 /` wraps, as with the other operators
+binop!(bi_div, |a, b| {
+    check_divisor(b)?;
+    a.wrapping_div(b)
+});
+
+binop!(bi_mod, |a, b| {
+    check_divisor(b)?;
+    a.wrapping_rem(b)
+});
+
+/// `( a b -- rem quot )`, truncated as with `/` and `mod`
+pub fn bi_divmod<BuiltinTok, SeqTok, Sdata, Sexec, O>(
     ctxt: &mut Runtime<BuiltinTok, SeqTok, Sdata, Sexec, O>,
 ) -> Result<(), Error>
 where
@@ -75,11 +579,26 @@ where
     BuiltinTok: Clone,
     O: Write,
 {
-    writeln!(&mut ctxt.cur_output)?;
-    Ok(())
+    let [a, b] = ctxt.data_stk.pop2()?;
+    check_divisor(b)?;
+    ctxt.data_stk.push(a.wrapping_rem(b))?;
+    ctxt.data_stk.push(a.wrapping_div(b))
+}
+
+/// Consume the entire data stack, combining all items with `f`, and push
+/// the result. Fails on an empty stack, as there is no sensible result
+fn reduce_all<Sdata>(stk: &mut Sdata, f: fn(i32, i32) -> i32) -> Result<(), Error>
+where
+    Sdata: Stack<Item = i32>,
+{
+    let mut acc = stk.pop().map_err(|_| Error::DataStackEmpty)?;
+    while !stk.is_empty() {
+        acc = f(acc, stk.pop()?);
+    }
+    stk.push(acc)
 }
 
-pub fn bi_lt<BuiltinTok, SeqTok, Sdata, Sexec, O>(
+pub fn bi_sum<BuiltinTok, SeqTok, Sdata, Sexec, O>(
     ctxt: &mut Runtime<BuiltinTok, SeqTok, Sdata, Sexec, O>,
 ) -> Result<(), Error>
 where
@@ -89,13 +608,10 @@ where
     BuiltinTok: Clone,
     O: Write,
 {
-    let val2 = ctxt.data_stk.pop()?;
-    let val1 = ctxt.data_stk.pop()?;
-    ctxt.data_stk.push(if val1 < val2 { -1 } else { 0 })?;
-    Ok(())
+    reduce_all(&mut ctxt.data_stk, i32::wrapping_add)
 }
 
-pub fn bi_gt<BuiltinTok, SeqTok, Sdata, Sexec, O>(
+pub fn bi_smax<BuiltinTok, SeqTok, Sdata, Sexec, O>(
     ctxt: &mut Runtime<BuiltinTok, SeqTok, Sdata, Sexec, O>,
 ) -> Result<(), Error>
 where
@@ -105,14 +621,27 @@ where
     BuiltinTok: Clone,
     O: Write,
 {
-    let val2 = ctxt.data_stk.pop()?;
-    let val1 = ctxt.data_stk.pop()?;
-    ctxt.data_stk.push(if val1 > val2 { -1 } else { 0 })?;
-    Ok(())
+    reduce_all(&mut ctxt.data_stk, i32::max)
 }
 
-pub fn bi_retstk_push<BuiltinTok, SeqTok, Sdata, Sexec, O>(
+pub fn bi_smin<BuiltinTok, SeqTok, Sdata, Sexec, O>(
+    ctxt: &mut Runtime<BuiltinTok, SeqTok, Sdata, Sexec, O>,
+) -> Result<(), Error>
+where
+    Sdata: Stack<Item = i32>,
+    Sexec: ExecutionStack<BuiltinTok, SeqTok>,
+    SeqTok: Clone,
+    BuiltinTok: Clone,
+    O: Write,
+{
+    reduce_all(&mut ctxt.data_stk, i32::min)
+}
+
+/// Pop a character code, and push whether it is in the given class. Codes
+/// outside of the ASCII range are never in any class
+fn char_class<BuiltinTok, SeqTok, Sdata, Sexec, O>(
     ctxt: &mut Runtime<BuiltinTok, SeqTok, Sdata, Sexec, O>,
+    class: fn(&u8) -> bool,
 ) -> Result<(), Error>
 where
     Sdata: Stack<Item = i32>,
@@ -122,11 +651,12 @@ where
     O: Write,
 {
     let val = ctxt.data_stk.pop()?;
-    ctxt.ret_stk.push(val)?;
+    let is = u8::try_from(val).map(|c| class(&c)).unwrap_or(false);
+    ctxt.data_stk.push(ctxt.flag(is))?;
     Ok(())
 }
 
-pub fn bi_retstk_pop<BuiltinTok, SeqTok, Sdata, Sexec, O>(
+pub fn bi_is_digit<BuiltinTok, SeqTok, Sdata, Sexec, O>(
     ctxt: &mut Runtime<BuiltinTok, SeqTok, Sdata, Sexec, O>,
 ) -> Result<(), Error>
 where
@@ -136,12 +666,10 @@ where
     BuiltinTok: Clone,
     O: Write,
 {
-    let val = ctxt.ret_stk.pop()?;
-    ctxt.data_stk.push(val)?;
-    Ok(())
+    char_class(ctxt, u8::is_ascii_digit)
 }
 
-pub fn bi_eq<BuiltinTok, SeqTok, Sdata, Sexec, O>(
+pub fn bi_is_alpha<BuiltinTok, SeqTok, Sdata, Sexec, O>(
     ctxt: &mut Runtime<BuiltinTok, SeqTok, Sdata, Sexec, O>,
 ) -> Result<(), Error>
 where
@@ -151,13 +679,11 @@ where
     BuiltinTok: Clone,
     O: Write,
 {
-    let val1 = ctxt.data_stk.pop()?;
-    let val2 = ctxt.data_stk.pop()?;
-    ctxt.data_stk.push(if val1 == val2 { -1 } else { 0 })?;
-    Ok(())
+    char_class(ctxt, u8::is_ascii_alphabetic)
 }
 
-pub fn bi_add<BuiltinTok, SeqTok, Sdata, Sexec, O>(
+/// Space, tab, and newline (including carriage return)
+pub fn bi_is_space<BuiltinTok, SeqTok, Sdata, Sexec, O>(
     ctxt: &mut Runtime<BuiltinTok, SeqTok, Sdata, Sexec, O>,
 ) -> Result<(), Error>
 where
@@ -167,10 +693,78 @@ where
     BuiltinTok: Clone,
     O: Write,
 {
-    let val1 = ctxt.data_stk.pop()?;
-    let val2 = ctxt.data_stk.pop()?;
-    ctxt.data_stk.push(val1.wrapping_add(val2))?;
-    Ok(())
+    char_class(ctxt, |c| matches!(c, b' ' | b'\t' | b'\n' | b'\r'))
+}
+
+/// Pop a bit position and a value, returning the value and a mask with only
+/// that bit set. Positions outside of `0..32` fail with `Error::BadMath`,
+/// leaving both on the stack
+fn pop_bit<Sdata>(stk: &mut Sdata) -> Result<(i32, i32), Error>
+where
+    Sdata: Stack<Item = i32>,
+{
+    let n = *stk.peek_back(0)?;
+    if !(0..32).contains(&n) {
+        return Err(Error::BadMath);
+    }
+    let [val, n] = stk.pop2()?;
+    Ok((val, (1u32 << n) as i32))
+}
+
+/// Check a shift count. As with bit positions, counts outside of `0..32`
+/// fail with `Error::BadMath`, rather than being masked
+fn shift_count(n: i32) -> Result<u32, Error> {
+    match n {
+        0..=31 => Ok(n as u32),
+        _ => Err(Error::BadMath),
+    }
+}
+
+binop!(bi_lshift, |a, n| ((a as u32) << shift_count(n)?) as i32);
+
+// A logical shift, filling with zeroes even for negative values
+binop!(bi_rshift, |a, n| ((a as u32) >> shift_count(n)?) as i32);
+
+pub fn bi_bit_set<BuiltinTok, SeqTok, Sdata, Sexec, O>(
+    ctxt: &mut Runtime<BuiltinTok, SeqTok, Sdata, Sexec, O>,
+) -> Result<(), Error>
+where
+    Sdata: Stack<Item = i32>,
+    Sexec: ExecutionStack<BuiltinTok, SeqTok>,
+    SeqTok: Clone,
+    BuiltinTok: Clone,
+    O: Write,
+{
+    let (val, mask) = pop_bit(&mut ctxt.data_stk)?;
+    ctxt.data_stk.push(val | mask)
+}
+
+pub fn bi_bit_clr<BuiltinTok, SeqTok, Sdata, Sexec, O>(
+    ctxt: &mut Runtime<BuiltinTok, SeqTok, Sdata, Sexec, O>,
+) -> Result<(), Error>
+where
+    Sdata: Stack<Item = i32>,
+    Sexec: ExecutionStack<BuiltinTok, SeqTok>,
+    SeqTok: Clone,
+    BuiltinTok: Clone,
+    O: Write,
+{
+    let (val, mask) = pop_bit(&mut ctxt.data_stk)?;
+    ctxt.data_stk.push(val & !mask)
+}
+
+pub fn bi_bit_test<BuiltinTok, SeqTok, Sdata, Sexec, O>(
+    ctxt: &mut Runtime<BuiltinTok, SeqTok, Sdata, Sexec, O>,
+) -> Result<(), Error>
+where
+    Sdata: Stack<Item = i32>,
+    Sexec: ExecutionStack<BuiltinTok, SeqTok>,
+    SeqTok: Clone,
+    BuiltinTok: Clone,
+    O: Write,
+{
+    let (val, mask) = pop_bit(&mut ctxt.data_stk)?;
+    ctxt.data_stk.push(ctxt.flag(val & mask != 0))
 }
 
 pub fn bi_dup<BuiltinTok, SeqTok, Sdata, Sexec, O>(
@@ -213,12 +807,11 @@ where
     BuiltinTok: Clone,
     O: Write,
 {
-    let val1 = ctxt.data_stk.pop()?;
-    let val2 = ctxt.data_stk.pop()?;
-    ctxt.data_stk.push(val2)?;
+    let [val1, val2] = ctxt.data_stk.pop2()?;
     ctxt.data_stk.push(val1)?;
     ctxt.data_stk.push(val2)?;
     ctxt.data_stk.push(val1)?;
+    ctxt.data_stk.push(val2)?;
     Ok(())
 }
 
@@ -232,8 +825,7 @@ where
     BuiltinTok: Clone,
     O: Write,
 {
-    let top = ctxt.ret_stk.pop()?;
-    let bot = ctxt.ret_stk.pop()?;
+    let [bot, top] = ctxt.ret_stk.pop2()?;
     ctxt.ret_stk.push(top)?;
     ctxt.ret_stk.push(bot)?;
 
@@ -250,14 +842,30 @@ where
     BuiltinTok: Clone,
     O: Write,
 {
-    let top = ctxt.data_stk.pop()?;
-    let bot = ctxt.data_stk.pop()?;
+    let [bot, top] = ctxt.data_stk.pop2()?;
     ctxt.data_stk.push(top)?;
     ctxt.data_stk.push(bot)?;
 
     Ok(())
 }
 
+/// Read the depth argument of `pick` or `roll` from the top of the stack,
+/// without consuming it. Negative depths, or depths that reach past the
+/// bottom of the stack, are a `DataStackUnderflow`, and leave the stack
+/// untouched.
+fn depth_arg<S: Stack<Item = i32>>(stk: &S) -> Result<usize, Error> {
+    let back: usize = (*stk.peek_back(0)?)
+        .try_into()
+        .map_err(|_| Error::DataStackUnderflow)?;
+
+    // The argument itself is still on the stack
+    if back + 1 >= stk.len() {
+        return Err(Error::DataStackUnderflow);
+    }
+
+    Ok(back)
+}
+
 pub fn bi_pick<BuiltinTok, SeqTok, Sdata, Sexec, O>(
     ctxt: &mut Runtime<BuiltinTok, SeqTok, Sdata, Sexec, O>,
 ) -> Result<(), Error>
@@ -268,8 +876,9 @@ where
     BuiltinTok: Clone,
     O: Write,
 {
-    let top = ctxt.data_stk.pop()?;
-    let val = *ctxt.data_stk.peek_back(top.try_into().map_err(|_| Error::DataStackUnderflow)?)?;
+    let back = depth_arg(&ctxt.data_stk)?;
+    ctxt.data_stk.pop()?;
+    let val = *ctxt.data_stk.peek_back(back)?;
     ctxt.data_stk.push(val)?;
 
     Ok(())
@@ -285,13 +894,236 @@ where
     BuiltinTok: Clone,
     O: Write,
 {
-    let top = ctxt.data_stk.pop()?;
-    let val = ctxt.data_stk.pop_back(top.try_into().map_err(|_| Error::DataStackUnderflow)?)?;
+    let back = depth_arg(&ctxt.data_stk)?;
+    ctxt.data_stk.pop()?;
+    let val = ctxt.data_stk.pop_back(back)?;
     ctxt.data_stk.push(val)?;
 
     Ok(())
 }
 
+/// The memory cell at `addr`, or `Error::BadAddress` if no such cell has been
+/// allocated
+fn mem_cell<BuiltinTok, SeqTok, Sdata, Sexec, O>(
+    ctxt: &mut Runtime<BuiltinTok, SeqTok, Sdata, Sexec, O>,
+    addr: i32,
+) -> Result<&mut i32, Error>
+where
+    Sdata: Stack<Item = i32>,
+    Sexec: ExecutionStack<BuiltinTok, SeqTok>,
+    SeqTok: Clone,
+    BuiltinTok: Clone,
+    O: Write,
+{
+    let idx = usize::try_from(addr).map_err(|_| Error::BadAddress)?;
+    ctxt.mem
+        .as_mut_slice()
+        .get_mut(idx)
+        .ok_or(Error::BadAddress)
+}
+
+/// `( val addr -- )` Store a value in a memory cell
+pub fn bi_store<BuiltinTok, SeqTok, Sdata, Sexec, O>(
+    ctxt: &mut Runtime<BuiltinTok, SeqTok, Sdata, Sexec, O>,
+) -> Result<(), Error>
+where
+    Sdata: Stack<Item = i32>,
+    Sexec: ExecutionStack<BuiltinTok, SeqTok>,
+    SeqTok: Clone,
+    BuiltinTok: Clone,
+    O: Write,
+{
+    let addr = *ctxt.data_stk.peek_back(0)?;
+    let val = *ctxt.data_stk.peek_back(1)?;
+    *mem_cell(ctxt, addr)? = val;
+    ctxt.data_stk.pop2()?;
+    Ok(())
+}
+
+/// `( delta addr -- )` Add to the value of a memory cell, wrapping on
+/// overflow
+pub fn bi_plus_store<BuiltinTok, SeqTok, Sdata, Sexec, O>(
+    ctxt: &mut Runtime<BuiltinTok, SeqTok, Sdata, Sexec, O>,
+) -> Result<(), Error>
+where
+    Sdata: Stack<Item = i32>,
+    Sexec: ExecutionStack<BuiltinTok, SeqTok>,
+    SeqTok: Clone,
+    BuiltinTok: Clone,
+    O: Write,
+{
+    let addr = *ctxt.data_stk.peek_back(0)?;
+    let delta = *ctxt.data_stk.peek_back(1)?;
+    let cell = mem_cell(ctxt, addr)?;
+    *cell = cell.wrapping_add(delta);
+    ctxt.data_stk.pop2()?;
+    Ok(())
+}
+
+/// `( lo hi addr -- )` Store a pair in two consecutive memory cells. As in
+/// standard Forth, `hi` goes in the cell at `addr`, and `lo` in the next.
+/// Nothing is stored unless both cells exist
+pub fn bi_2store<BuiltinTok, SeqTok, Sdata, Sexec, O>(
+    ctxt: &mut Runtime<BuiltinTok, SeqTok, Sdata, Sexec, O>,
+) -> Result<(), Error>
+where
+    Sdata: Stack<Item = i32>,
+    Sexec: ExecutionStack<BuiltinTok, SeqTok>,
+    SeqTok: Clone,
+    BuiltinTok: Clone,
+    O: Write,
+{
+    let addr = *ctxt.data_stk.peek_back(0)?;
+    let hi = *ctxt.data_stk.peek_back(1)?;
+    let lo = *ctxt.data_stk.peek_back(2)?;
+    let next = addr.checked_add(1).ok_or(Error::BadAddress)?;
+    mem_cell(ctxt, next)?;
+    *mem_cell(ctxt, addr)? = hi;
+    *mem_cell(ctxt, next)? = lo;
+    ctxt.data_stk.pop3()?;
+    Ok(())
+}
+
+/// `( addr -- lo hi )` Fetch a pair stored by `2!`
+pub fn bi_2fetch<BuiltinTok, SeqTok, Sdata, Sexec, O>(
+    ctxt: &mut Runtime<BuiltinTok, SeqTok, Sdata, Sexec, O>,
+) -> Result<(), Error>
+where
+    Sdata: Stack<Item = i32>,
+    Sexec: ExecutionStack<BuiltinTok, SeqTok>,
+    SeqTok: Clone,
+    BuiltinTok: Clone,
+    O: Write,
+{
+    let addr = *ctxt.data_stk.last()?;
+    let next = addr.checked_add(1).ok_or(Error::BadAddress)?;
+    let lo = *mem_cell(ctxt, next)?;
+    let hi = *mem_cell(ctxt, addr)?;
+    ctxt.data_stk.pop()?;
+    ctxt.data_stk.push(lo)?;
+    ctxt.data_stk.push(hi)
+}
+
+/// `( n -- n )` The size of `n` cells, in address units. Every cell is a
+/// single address here, so this leaves `n` unchanged, but keeps programs
+/// portable to Forths with byte addresses
+pub fn bi_cells<BuiltinTok, SeqTok, Sdata, Sexec, O>(
+    ctxt: &mut Runtime<BuiltinTok, SeqTok, Sdata, Sexec, O>,
+) -> Result<(), Error>
+where
+    Sdata: Stack<Item = i32>,
+    Sexec: ExecutionStack<BuiltinTok, SeqTok>,
+    SeqTok: Clone,
+    BuiltinTok: Clone,
+    O: Write,
+{
+    ctxt.data_stk.last()?;
+    Ok(())
+}
+
+/// `( n -- )` Allocate `n` more memory cells, set to zero, directly after the
+/// most recently allocated ones. Nothing is allocated if there isn't room for
+/// all of them
+pub fn bi_allot<BuiltinTok, SeqTok, Sdata, Sexec, O>(
+    ctxt: &mut Runtime<BuiltinTok, SeqTok, Sdata, Sexec, O>,
+) -> Result<(), Error>
+where
+    Sdata: Stack<Item = i32>,
+    Sexec: ExecutionStack<BuiltinTok, SeqTok>,
+    SeqTok: Clone,
+    BuiltinTok: Clone,
+    O: Write,
+{
+    let count = usize::try_from(*ctxt.data_stk.last()?).map_err(|_| Error::BadMath)?;
+
+    for pushed in 0..count {
+        if ctxt.mem.push(0).is_err() {
+            for _ in 0..pushed {
+                ctxt.mem.pop()?;
+            }
+            return Err(Error::OutOfMemory);
+        }
+    }
+
+    ctxt.data_stk.pop()?;
+    Ok(())
+}
+
+/// `( addr -- val )` Fetch the value of a memory cell
+pub fn bi_fetch<BuiltinTok, SeqTok, Sdata, Sexec, O>(
+    ctxt: &mut Runtime<BuiltinTok, SeqTok, Sdata, Sexec, O>,
+) -> Result<(), Error>
+where
+    Sdata: Stack<Item = i32>,
+    Sexec: ExecutionStack<BuiltinTok, SeqTok>,
+    SeqTok: Clone,
+    BuiltinTok: Clone,
+    O: Write,
+{
+    let addr = *ctxt.data_stk.last()?;
+    let val = *mem_cell(ctxt, addr)?;
+    ctxt.data_stk.pop()?;
+    ctxt.data_stk.push(val)
+}
+
+/// Push a copy of the index of the innermost `do ... loop`. The loop keeps
+/// its index and limit on the return stack, with the limit on top
+pub fn bi_i<BuiltinTok, SeqTok, Sdata, Sexec, O>(
+    ctxt: &mut Runtime<BuiltinTok, SeqTok, Sdata, Sexec, O>,
+) -> Result<(), Error>
+where
+    Sdata: Stack<Item = i32>,
+    Sexec: ExecutionStack<BuiltinTok, SeqTok>,
+    SeqTok: Clone,
+    BuiltinTok: Clone,
+    O: Write,
+{
+    let idx = *ctxt.ret_stk.peek_back(1)?;
+    ctxt.data_stk.push(idx)
+}
+
+/// Push a copy of the index of the next-outer `do ... loop`, found below the
+/// innermost loop's index and limit
+pub fn bi_j<BuiltinTok, SeqTok, Sdata, Sexec, O>(
+    ctxt: &mut Runtime<BuiltinTok, SeqTok, Sdata, Sexec, O>,
+) -> Result<(), Error>
+where
+    Sdata: Stack<Item = i32>,
+    Sexec: ExecutionStack<BuiltinTok, SeqTok>,
+    SeqTok: Clone,
+    BuiltinTok: Clone,
+    O: Write,
+{
+    let idx = *ctxt.ret_stk.peek_back(3)?;
+    ctxt.data_stk.push(idx)
+}
+
+/// List every builtin and user-defined word. The names live in the
+/// compiler's dictionary, which builtins can't see, so this asks the host to
+/// print them. A std `Context` does so; hosts without a dictionary of names,
+/// such as a device running a loaded program, print nothing
+pub fn bi_words<BuiltinTok, SeqTok, Sdata, Sexec, O>(
+    ctxt: &mut Runtime<BuiltinTok, SeqTok, Sdata, Sexec, O>,
+) -> Result<(), Error>
+where
+    Sdata: Stack<Item = i32>,
+    Sexec: ExecutionStack<BuiltinTok, SeqTok>,
+    SeqTok: Clone,
+    BuiltinTok: Clone,
+    O: Write,
+{
+    ctxt.request_host(HostRequest::ListWords);
+    Ok(())
+}
+
+/// The loop counter step of `do ... loop`.
+///
+/// The index is incremented before being compared against the limit, so the
+/// body always runs at least once, and a loop from `start` runs until the index
+/// reaches `limit`. If the limit is not above the start, the index can never
+/// reach it, and the loop ends with `Error::BadMath` once the index would
+/// overflow past `i32::MAX`. This bounds any loop to at most
+/// `i32::MAX - start` iterations; the index never silently wraps.
 pub fn bi_priv_loop<BuiltinTok, SeqTok, Sdata, Sexec, O>(
     ctxt: &mut Runtime<BuiltinTok, SeqTok, Sdata, Sexec, O>,
 ) -> Result<(), Error>
@@ -302,18 +1134,52 @@ where
     BuiltinTok: Clone,
     O: Write,
 {
-    let lmt = ctxt.ret_stk.pop()?;
-    let mut idx = ctxt.ret_stk.pop()?;
+    let [mut idx, lmt] = ctxt.ret_stk.pop2()?;
 
     idx = idx.checked_add(1).ok_or(Error::BadMath)?;
 
     if idx == lmt {
-        ctxt.data_stk.push(-1)?;
+        ctxt.data_stk.push(ctxt.flag(true))?;
     } else {
-        ctxt.data_stk.push(0)?;
+        ctxt.data_stk.push(ctxt.flag(false))?;
         ctxt.ret_stk.push(idx)?;
         ctxt.ret_stk.push(lmt)?;
     }
 
     Ok(())
 }
+
+/// The loop counter step of `do ... +loop`, taking the increment from the
+/// data stack.
+///
+/// As in standard Forth, the loop ends once the index crosses the boundary
+/// between `limit - 1` and `limit`, in either direction. A positive step stops
+/// before the index reaches `limit`, while a negative step still runs the body
+/// with the index at `limit`. A step of zero never ends the loop.
+pub fn bi_priv_plus_loop<BuiltinTok, SeqTok, Sdata, Sexec, O>(
+    ctxt: &mut Runtime<BuiltinTok, SeqTok, Sdata, Sexec, O>,
+) -> Result<(), Error>
+where
+    Sdata: Stack<Item = i32>,
+    Sexec: ExecutionStack<BuiltinTok, SeqTok>,
+    SeqTok: Clone,
+    BuiltinTok: Clone,
+    O: Write,
+{
+    let step = ctxt.data_stk.pop()?;
+    let [idx, lmt] = ctxt.ret_stk.pop2()?;
+
+    // Offset the distance from the limit so that the boundary sits at the
+    // edge of the `i32` range: crossing it is exactly when the add overflows
+    let dist = idx.wrapping_sub(lmt) ^ i32::MIN;
+
+    if dist.checked_add(step).is_none() {
+        ctxt.data_stk.push(ctxt.flag(true))?;
+    } else {
+        ctxt.data_stk.push(ctxt.flag(false))?;
+        ctxt.ret_stk.push(idx.wrapping_add(step))?;
+        ctxt.ret_stk.push(lmt)?;
+    }
+
+    Ok(())
+}
@@ -1,19 +1,49 @@
 use std::collections::BTreeMap;
+use std::io::Write;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use crate::{
     ser_de::{SerDict, SerWord},
     std_rt::{
-        new_runtime, ser_srw, BuiltinToken, NamedStdRuntimeWord, SerContext, StdFuncSeq,
+        new_runtime, ser_srw, Builtin, BuiltinToken, NamedStdRuntimeWord, SerContext, StdFuncSeq,
         StdRuntime, StdRuntimeWord, StdVecStack,
     },
-    Error, RuntimeWord, StepResult, VerbSeqInner,
+    Error, ExecutionStack, HostRequest, RuntimeWord, Stack, StepResult, VerbSeqInner, WhichToken,
+    WordId,
 };
 
+/// The start of the sequence token of each "bare" (non-definition) line, which
+/// is followed by a number unique to the line. This can never collide with a
+/// user-defined word, as words are split on whitespace.
+const EPHEMERAL_PREFIX: &str = " line ";
+
 pub struct Dict {
     pub bis: BTreeMap<String, BuiltinToken>,
     pub data: BTreeMap<String, StdFuncSeq>,
-    pub(crate) shame_idx: usize,
+
+    /// When set, the original tokens of each definition are kept in `source`.
+    /// This is off by default, as it roughly doubles the memory used per word
+    pub retain_source: bool,
+    pub source: BTreeMap<String, Vec<String>>,
+
+    /// Every definition, oldest first, used by `forget`. A redefined word is
+    /// added again, keeping the definition it replaced
+    order: Vec<Definition>,
+}
+
+/// A definition in the `Dict`, as needed to undo it with `forget`
+struct Definition {
+    name: String,
+
+    /// The length of the runtime memory before the word was defined. Any cells
+    /// allotted since are freed when it is forgotten
+    here: usize,
+
+    /// The definition and source this one replaced, which are restored when
+    /// it is forgotten
+    shadowed: Option<StdFuncSeq>,
+    shadowed_source: Option<Vec<String>>,
 }
 
 impl Dict {
@@ -21,8 +51,81 @@ impl Dict {
         Self {
             bis: BTreeMap::new(),
             data: BTreeMap::new(),
-            shame_idx: 0,
+            retain_source: false,
+            source: BTreeMap::new(),
+            order: Vec::new(),
+        }
+    }
+
+    /// The original tokens of a definition, including the leading `:` and
+    /// trailing `;`. Only available if `retain_source` was set when the
+    /// word was defined
+    pub fn source(&self, name: &str) -> Option<&[String]> {
+        self.source.get(name).map(Vec::as_slice)
+    }
+
+    /// Define a word, where `here` is the current length of the runtime memory
+    fn insert(&mut self, name: String, seq: StdFuncSeq, source: &[String], here: usize) {
+        let source = self.retain_source.then(|| source.to_vec());
+        self.define(name, seq, source, here);
+    }
+
+    fn define(&mut self, name: String, seq: StdFuncSeq, source: Option<Vec<String>>, here: usize) {
+        let shadowed = self.data.insert(name.clone(), seq);
+        let shadowed_source = match source {
+            Some(source) => self.source.insert(name.clone(), source),
+            None => self.source.remove(&name),
+        };
+        self.order.push(Definition {
+            name,
+            here,
+            shadowed,
+            shadowed_source,
+        });
+    }
+
+    /// Undo the latest definition of a word, along with every definition made
+    /// after it. Redefined words go back to what they replaced. Returns the
+    /// length the runtime memory had before the word was defined.
+    ///
+    /// This fails with `Error::WordInUse`, changing nothing, if any word that
+    /// would be removed is still called by a word that would remain
+    fn forget(&mut self, name: &str) -> Result<usize, Error> {
+        let pos = self
+            .order
+            .iter()
+            .rposition(|def| def.name == name)
+            .ok_or(Error::UnknownWord)?;
+
+        let mut data = self.data.clone();
+        for def in self.order[pos..].iter().rev() {
+            match &def.shadowed {
+                Some(seq) => data.insert(def.name.clone(), seq.clone()),
+                None => data.remove(&def.name),
+            };
+        }
+
+        let in_use = data.values().any(|seq| {
+            seq.inner.iter().any(|word| match &word.word {
+                RuntimeWord::VerbSeq(vsi) => {
+                    self.data.contains_key(&vsi.tok) && !data.contains_key(&vsi.tok)
+                }
+                _ => false,
+            })
+        });
+        if in_use {
+            return Err(Error::WordInUse);
+        }
+
+        self.data = data;
+        let here = self.order[pos].here;
+        for def in self.order.drain(pos..).rev() {
+            match def.shadowed_source {
+                Some(source) => self.source.insert(def.name, source),
+                None => self.source.remove(&def.name),
+            };
         }
+        Ok(here)
     }
 
     pub fn serialize(&self) -> SerDict {
@@ -46,11 +149,249 @@ impl Dict {
             bis: ctxt.bis,
         }
     }
+
+    /// Serialize directly to `out`, producing exactly the same bytes as
+    /// postcard-encoding the result of `serialize`. Only one sequence is
+    /// encoded at a time, rather than building the whole `SerDict` in memory
+    pub fn serialize_to_writer<W: Write>(&self, out: &mut W) -> Result<(), Error> {
+        let mut ctxt = SerContext::new();
+
+        // The first pass only assigns the indices of each builtin and
+        // sequence, in the same order as `serialize`
+        for (word, val) in self.data.iter() {
+            for rtw in val.inner.iter() {
+                ctxt.encode_rtw(rtw);
+            }
+            ctxt.intern_seq(word);
+        }
+
+        // data
+        write_varint(out, ctxt.seqs.len())?;
+        for word in ctxt.seqs.clone() {
+            let seq = self.data.get(&word).ok_or(Error::InternalError)?;
+            write_varint(out, seq.inner.len())?;
+            for rtw in seq.inner.iter() {
+                let mut buf = [0u8; 16];
+                let used = postcard::to_slice(&ctxt.encode_rtw(rtw), &mut buf)
+                    .map_err(|_| Error::OutputFormat)?;
+                out.write_all(used).map_err(|_| Error::OutputFormat)?;
+            }
+        }
+
+        // data_map, which is always present
+        out.write_all(&[1]).map_err(|_| Error::OutputFormat)?;
+        write_strs(out, &ctxt.seqs)?;
+
+        // bis
+        write_strs(out, &ctxt.bis)
+    }
+}
+
+/// Write a length, in postcard's varint format
+fn write_varint<W: Write>(out: &mut W, mut val: usize) -> Result<(), Error> {
+    loop {
+        let byte = (val & 0x7F) as u8;
+        val >>= 7;
+        let byte = if val == 0 { byte } else { byte | 0x80 };
+        out.write_all(&[byte]).map_err(|_| Error::OutputFormat)?;
+        if val == 0 {
+            return Ok(());
+        }
+    }
+}
+
+/// Write a list of strings, in postcard's format
+fn write_strs<W: Write>(out: &mut W, strs: &[String]) -> Result<(), Error> {
+    write_varint(out, strs.len())?;
+    for s in strs {
+        write_varint(out, s.len())?;
+        out.write_all(s.as_bytes())
+            .map_err(|_| Error::OutputFormat)?;
+    }
+    Ok(())
+}
+
+/// Quote a word name for use as a DOT identifier
+fn dot_quote(name: &str) -> String {
+    let mut out = String::from("\"");
+    for c in name.chars() {
+        if c == '"' || c == '\\' {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out.push('"');
+    out
+}
+
+/// A single observable action taken by the runtime, as reported by
+/// `Context::step_event`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StepEvent {
+    /// A builtin word was executed
+    ExecutedBuiltin { name: String },
+
+    /// A call was made into a user-defined word
+    EnteredSeq { name: String },
+
+    /// A branch was taken within the current sequence, from the index of the
+    /// jump to the index of the next word to be executed
+    Jumped { from: usize, to: usize },
+
+    /// A literal was pushed to the data stack
+    PushedLiteral { val: i32 },
+
+    /// There is nothing left to execute
+    Done,
+
+    /// Execution was stopped early by `quit`
+    Quit,
+
+    /// A `key` is waiting for `Runtime::provide_input`
+    NeedInput,
+}
+
+/// The first failure in `Context::evaluate_lines`
+#[derive(Debug)]
+pub struct LineError {
+    /// The line that failed, counting from one
+    pub line: usize,
+    pub error: Error,
+
+    /// Everything printed before the failure, including by the failing line
+    pub output: String,
+}
+
+/// A problem found while evaluating, that didn't stop evaluation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Warning {
+    pub message: String,
+
+    /// The line that caused the warning, counting from one, if it was
+    /// evaluated by `Context::evaluate_lines`
+    pub line: Option<usize>,
+}
+
+/// The decision made by a `Context::dispatch_filter` for a single builtin
+#[derive(Clone, Copy)]
+pub enum Dispatch {
+    /// Execute the builtin as normal
+    Allow,
+
+    /// Stop execution with `Error::BuiltinDenied`
+    Deny,
+
+    /// Execute the given function in place of the builtin
+    Substitute(fn(&mut StdRuntime) -> Result<(), Error>),
+}
+
+/// The number of calls to, and time spent in, a single word
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct WordProfile {
+    pub calls: u64,
+
+    /// Wall-clock time between entering and leaving the word, including the
+    /// time spent in any words it calls
+    pub elapsed: Duration,
+}
+
+/// Per-word statistics, collected by `Context::run_blocking` when enabled
+#[derive(Debug, Default)]
+pub struct Profiler {
+    pub words: BTreeMap<String, WordProfile>,
+
+    /// Words that have been entered but not yet left, with the depth of the
+    /// flow stack while they are running
+    frames: Vec<(String, usize, Instant)>,
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&WordProfile> {
+        self.words.get(name)
+    }
+
+    fn enter(&mut self, name: String, depth: usize) {
+        self.words.entry(name.clone()).or_default().calls += 1;
+        self.frames.push((name, depth, Instant::now()));
+    }
+
+    /// Close any frames that are deeper than the flow stack now is
+    fn leave_above(&mut self, depth: usize) {
+        while let Some((name, fdepth, start)) = self.frames.pop() {
+            if fdepth <= depth {
+                self.frames.push((name, fdepth, start));
+                break;
+            }
+            self.words.entry(name).or_default().elapsed += start.elapsed();
+        }
+    }
+}
+
+/// The stacks of a runtime before a single step, used to undo that step
+struct Snapshot {
+    data_stk: StdVecStack<i32>,
+    ret_stk: StdVecStack<i32>,
+    flow_stk: StdVecStack<StdRuntimeWord>,
 }
 
 pub struct Context {
     pub rt: StdRuntime,
     pub dict: Dict,
+
+    /// When set, only definitions may be evaluated. Any bare line is
+    /// rejected with `Error::NotADefinition`, rather than being executed
+    pub compile_only: bool,
+
+    /// The tokens that start and end a definition, `:` and `;` by default.
+    /// These may be changed (e.g. to `def` and `end`) for use as a DSL
+    pub def_start: String,
+    pub def_end: String,
+
+    /// When set, this is consulted with the name of every builtin before it
+    /// is executed by `run_blocking` or `step_event`, allowing a host to deny
+    /// or replace builtins when running untrusted programs
+    pub dispatch_filter: Option<fn(&str) -> Dispatch>,
+
+    /// When set, the arity of each builtin (see `builtin_arity`) is checked
+    /// against the depth of the data stack before it is executed by
+    /// `run_blocking` or `step_event`, as with `?stack`. A builtin that would
+    /// underflow fails with `Error::DataStackUnderflow` without running, and
+    /// without touching the stacks. Its name is kept in `underflow_word`
+    pub check_stack: bool,
+
+    /// When set, `run_blocking` records the calls to, and time spent in,
+    /// every builtin and sequence it executes
+    pub profiler: Option<Profiler>,
+
+    /// The number of steps taken by `step_event` that can be undone with
+    /// `step_back`. This is zero (disabled) by default, as a copy of every
+    /// stack is kept for each step
+    pub history_len: usize,
+    history: VecDeque<Snapshot>,
+
+    /// For each `[if]` being evaluated, whether its current branch is being
+    /// compiled, or `None` if it is inside of a region that is skipped
+    conditions: Vec<Option<bool>>,
+
+    /// The compiled form of each evaluated bare line, by sequence token. These
+    /// are never inserted into the `dict`, and are dropped by the next
+    /// evaluation once they are no longer on the flow stack
+    ephemeral: BTreeMap<String, StdFuncSeq>,
+    next_ephemeral: u64,
+
+    /// Every warning since the last `clear_warnings`, oldest first
+    warnings: Vec<Warning>,
+
+    /// The token that caused the last evaluation to fail with
+    /// `Error::UnknownWord`
+    unknown_word: Option<String>,
+
+    /// The builtin stopped by `check_stack` in the last evaluation
+    underflow_word: Option<String>,
 }
 
 impl Context {
@@ -58,17 +399,17 @@ impl Context {
         let data_map = if let Some(dm) = data.data_map.as_ref() {
             dm.clone()
         } else {
-            eprintln!("Error: dict has no name map! Refusing to load.");
+            self.warn("dict has no name map! Refusing to load.");
             return;
         };
 
         if !data.bis.iter().all(|bi| self.dict.bis.contains_key(bi)) {
-            eprintln!("Missing builtins! Refusing to load.");
+            self.warn("Missing builtins! Refusing to load.");
             return;
         }
 
         if data_map.len() != data.data.len() {
-            eprintln!("Data map size mismatch! Refusing to load.");
+            self.warn("Data map size mismatch! Refusing to load.");
             return;
         }
 
@@ -108,82 +449,817 @@ impl Context {
                 })
                 .collect::<Vec<_>>();
 
-            self.dict.data.insert(
+            self.dict.define(
                 name.clone(),
                 StdFuncSeq {
                     inner: Arc::new(cword),
                 },
+                None,
+                self.rt.mem.len(),
             );
         }
     }
 
     fn compile(&mut self, data: &[String]) -> Result<Vec<NamedStdRuntimeWord>, Error> {
-        let mut vd_data: VecDeque<String> = data
-            .iter()
-            .map(String::as_str)
-            .map(str::to_lowercase)
-            .collect();
+        let res = compile_tokens(data, &mut self.dict);
+        if let Err(Error::UnknownWord) = res {
+            self.unknown_word = find_unknown_word(data, &self.dict);
+        }
+        res
+    }
 
-        let munched = muncher(&mut vd_data);
-        assert!(vd_data.is_empty());
+    /// The word that could not be found when the last evaluation failed with
+    /// `Error::UnknownWord`, for a host to show to the user
+    pub fn unknown_word(&self) -> Option<&str> {
+        self.unknown_word.as_deref()
+    }
 
-        let conv: Vec<NamedStdRuntimeWord> = munched
-            .into_iter()
-            .map(|m| m.to_named_rt_words(&mut self.dict))
-            .flatten()
-            .collect();
+    /// The builtin that was stopped by `check_stack` when the last evaluation
+    /// failed with `Error::DataStackUnderflow`, for a host to show to the user
+    pub fn underflow_word(&self) -> Option<&str> {
+        self.underflow_word.as_deref()
+    }
 
-        Ok(conv)
+    /// Compile source made up only of definitions, one per line, returning
+    /// them ready to be serialized. Only the builtins of this context are
+    /// used: the `dict` is left untouched, and nothing is executed
+    pub fn compile_word(&self, src: &str) -> Result<SerDict, Error> {
+        let mut dict = Dict::new();
+        dict.bis = self.dict.bis.clone();
+
+        for line in src.lines() {
+            let data = strip_line_comment(tokenize(line));
+            match (data.first(), data.last()) {
+                (None, _) => continue,
+                (Some(f), Some(l)) if self.is_def_start(f) && self.is_def_end(l) => {}
+                _ => return Err(Error::NotADefinition),
+            }
+            if data.len() < 3 {
+                return Err(Error::MissingWordName);
+            }
+
+            let relevant = &data[2..][..data.len() - 3];
+            self.check_stray_tokens(relevant)?;
+
+            let name = data[1].to_lowercase();
+            let compiled = Arc::new(compile_tokens(relevant, &mut dict)?);
+            dict.insert(name, StdFuncSeq { inner: compiled }, &data, 0);
+        }
+
+        Ok(dict.serialize())
     }
 
+    /// Compile a single line, either storing it as a definition, or pushing it
+    /// to be executed. A `\` comments out the rest of the tokens given, so
+    /// hosts should pass one line of source at a time, split with `tokenize`
     pub fn evaluate(&mut self, data: Vec<String>) -> Result<(), Error> {
+        self.unknown_word = None;
+        self.underflow_word = None;
+        let data = self.conditional(strip_line_comment(data))?;
         match (data.first(), data.last()) {
-            (Some(f), Some(l)) if f == ":" && l == ";" => {
+            (Some(f), Some(l)) if self.is_def_start(f) && self.is_def_end(l) => {
                 // Must have ":", "$NAME", "$SOMETHING+", ";"
-                assert!(data.len() >= 3);
+                if data.len() < 3 {
+                    return Err(Error::MissingWordName);
+                }
 
                 let name = data[1].to_lowercase();
 
                 // TODO: Doesn't handle "empty" definitions
                 let relevant = &data[2..][..data.len() - 3];
+                self.check_stray_tokens(relevant)?;
 
-                // let compiled = Arc::new(self.compile(relevant)?);
-                let compiled = Arc::new(self.compile(relevant).unwrap());
+                let compiled = Arc::new(self.compile(relevant)?);
 
-                self.dict.data.insert(name, StdFuncSeq { inner: compiled });
+                if self.dict.data.contains_key(&name) {
+                    self.warn(format!("redefined `{}`", name));
+                }
+                let here = self.rt.mem.len();
+                self.dict
+                    .insert(name, StdFuncSeq { inner: compiled }, &data, here);
             }
             _ => {
+                let data = self.dictionary_words(&data)?;
+
                 // We should interpret this as a line to compile and run
                 // (but then discard, because it isn't bound in the dict)
-                // let temp_compiled = RuntimeWord::VerbSeq(StdFuncSeq { inner:  });
                 if !data.is_empty() {
-                    let name = format!("__{}", self.dict.shame_idx);
-                    // let comp = self.compile(&data)?;
-                    let comp = self.compile(&data).unwrap();
-                    self.dict.data.insert(
-                        name.clone(),
+                    if data.len() == 1 && self.is_def_start(&data[0]) {
+                        return Err(Error::MissingWordName);
+                    }
+                    self.check_stray_tokens(&data)?;
+
+                    if self.compile_only {
+                        return Err(Error::NotADefinition);
+                    }
+
+                    let comp = self.compile(&data)?;
+                    self.drop_finished_lines();
+                    let tok = format!("{}{}", EPHEMERAL_PREFIX, self.next_ephemeral);
+                    self.next_ephemeral += 1;
+
+                    self.ephemeral.insert(
+                        tok.clone(),
                         StdFuncSeq {
                             inner: Arc::new(comp),
                         },
                     );
-                    self.dict.shame_idx += 1;
-                    let temp_compiled = RuntimeWord::VerbSeq(VerbSeqInner::from_word(name));
-                    self.push_exec(temp_compiled);
+                    let temp_compiled = RuntimeWord::VerbSeq(VerbSeqInner::from_word(tok.clone()));
+                    if let Err(e) = self.push_exec(temp_compiled) {
+                        self.ephemeral.remove(&tok);
+                        return Err(e);
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Drop the compiled bare lines that can no longer be executed, as they
+    /// are not on the flow stack, nor on any copy of it kept for `step_back`
+    fn drop_finished_lines(&mut self) {
+        let history = self.history.iter().map(|snap| &snap.flow_stk);
+        let frames: Vec<&str> = std::iter::once(&self.rt.flow_stk)
+            .chain(history)
+            .flat_map(|stk| stk.data().iter())
+            .filter_map(|frame| match frame {
+                RuntimeWord::VerbSeq(seq) => Some(seq.tok.as_str()),
+                _ => None,
+            })
+            .collect();
+        self.ephemeral
+            .retain(|tok, _| frames.contains(&tok.as_str()));
+    }
+
+    /// Handle each `variable NAME` and `forget NAME` in a bare line. A
+    /// variable allocates a memory cell, and defines `NAME` as a word that
+    /// pushes its address. Returns the remaining tokens, which are compiled
+    /// and run as usual.
+    ///
+    /// These take effect as the line is evaluated, before any of the rest of
+    /// the line runs, wherever they appear in it. So in `5 allot variable x`,
+    /// `x` gets the cell before the five allotted, and `forget x x` fails as
+    /// `x` is gone before the line is compiled
+    fn dictionary_words(&mut self, data: &[String]) -> Result<Vec<String>, Error> {
+        let mut out = Vec::new();
+        let interp = interpreted_tokens(data);
+        let mut toks = data.iter().zip(interp);
+
+        while let Some((tok, interp)) = toks.next() {
+            if !interp {
+                out.push(tok.clone());
+                continue;
+            }
+            if tok.eq_ignore_ascii_case("forget") {
+                let (name, _) = toks.next().ok_or(Error::MissingWordName)?;
+                self.forget(name)?;
+                continue;
+            }
+            if !tok.eq_ignore_ascii_case("variable") {
+                out.push(tok.clone());
+                continue;
+            }
+            let (name, _) = toks.next().ok_or(Error::MissingWordName)?;
+            let name = name.to_lowercase();
+            if self.is_def_start(&name) || self.is_def_end(&name) {
+                return Err(Error::MissingWordName);
+            }
+
+            let addr = self.rt.mem.len() as i32;
+            self.rt.mem.push(0).map_err(|_| Error::OutOfMemory)?;
+
+            if self.dict.data.contains_key(&name) {
+                self.warn(format!("redefined `{}`", name));
+            }
+            let seq = StdFuncSeq {
+                inner: Arc::new(vec![NamedStdRuntimeWord {
+                    word: RuntimeWord::LiteralVal(addr),
+                    name: format!("LIT({})", addr),
+                }]),
+            };
+            let source = [tok.clone(), name.clone()];
+            self.dict.insert(name, seq, &source, addr as usize);
+        }
+
+        Ok(out)
+    }
+
+    /// Evaluate a single definition, returning the name it was defined with,
+    /// e.g. to then pass it to `call_word`
+    pub fn define_and_get(&mut self, src: &str) -> Result<String, Error> {
+        let data = strip_line_comment(tokenize(src));
+        let name = match (data.first(), data.get(1), data.last()) {
+            (Some(f), Some(name), Some(l)) if self.is_def_start(f) && self.is_def_end(l) => {
+                name.to_lowercase()
+            }
+            _ => return Err(Error::NotADefinition),
+        };
+
+        self.evaluate(data)?;
+        Ok(name)
+    }
+
+    /// Apply conditional compilation with `[if]`, `[else]` and `[then]`,
+    /// returning only the tokens that should be compiled. Each `[if]` takes
+    /// its flag from the data stack when it is evaluated, so the flag must be
+    /// pushed by an earlier line (or the host). Regions may span many lines
+    fn conditional(&mut self, data: Vec<String>) -> Result<Vec<String>, Error> {
+        let mut out = Vec::new();
+
+        for tok in data {
+            let active = self.conditions.iter().all(|c| *c == Some(true));
+
+            match tok.to_lowercase().as_str() {
+                "[if]" if active => {
+                    let flag = self.rt.data_stk.pop()?;
+                    self.conditions.push(Some(flag != 0));
+                }
+                "[if]" => self.conditions.push(None),
+                "[else]" => match self.conditions.last_mut() {
+                    Some(Some(cond)) => *cond = !*cond,
+                    Some(None) => {}
+                    None => return Err(Error::UnbalancedControl),
+                },
+                "[then]" => {
+                    self.conditions.pop().ok_or(Error::UnbalancedControl)?;
                 }
+                _ if active => out.push(tok),
+                _ => {}
+            }
+        }
+
+        Ok(out)
+    }
+
+    /// Evaluate and run each line in turn, as a script would be. Blank lines
+    /// are skipped. Returns all output produced, or stops at the first error.
+    /// A line that fails while running is unwound, unless a `key` is waiting
+    /// for `provide_input`, in which case it may be resumed with `run_blocking`
+    pub fn evaluate_lines(
+        &mut self,
+        lines: impl Iterator<Item = String>,
+    ) -> Result<String, LineError> {
+        let mut output = String::new();
+
+        for (idx, line) in lines.enumerate() {
+            let words = tokenize(&line);
+            if words.is_empty() {
+                continue;
+            }
+
+            let before = self.warnings.len();
+            let res = self.evaluate(words).and_then(|_| self.run_or_unwind());
+            for warning in &mut self.warnings[before..] {
+                warning.line.get_or_insert(idx + 1);
             }
+            output += &self.output();
+
+            if let Err(error) = res {
+                return Err(LineError {
+                    line: idx + 1,
+                    error,
+                    output,
+                });
+            }
+        }
+
+        Ok(output)
+    }
+
+    /// Compile and run a single bare expression to completion, returning the
+    /// contents of the data stack afterwards (bottom first). Definitions are
+    /// rejected with `Error::NotAnExpression`, so the `dict` is never modified.
+    /// If running fails, the stacks are unwound
+    pub fn eval_expr(&mut self, expr: &str) -> Result<Vec<i32>, Error> {
+        let data = tokenize(expr);
+
+        if matches!(data.first(), Some(f) if self.is_def_start(f)) {
+            return Err(Error::NotAnExpression);
+        }
+
+        self.evaluate(data)?;
+        self.run_or_unwind()?;
+
+        Ok(self.rt.data_stk.data().to_vec())
+    }
+
+    /// Run `src` as a bare expression on a data stack holding only `input`
+    /// (bottom first), returning the final data stack. The data stack is left
+    /// empty afterwards. This is mostly useful for testing the stack effect
+    /// of words, e.g. `with_stack_effect(&[3, 4], "+")` gives `[7]`
+    pub fn with_stack_effect(&mut self, input: &[i32], src: &str) -> Result<Vec<i32>, Error> {
+        self.rt.data_stk.clear();
+        for val in input {
+            self.rt.data_stk.push(*val)?;
+        }
+
+        let out = self.eval_expr(src);
+        self.rt.data_stk.clear();
+        out
+    }
+
+    /// Recompile the body of a single word from source, replacing any existing
+    /// definition. All other words, and the current state of the stacks, are
+    /// left untouched. Existing callers will use the new body on their next call.
+    ///
+    /// This is rejected with `Error::WordInUse` if the word is currently executing.
+    pub fn hot_reload(&mut self, name: &str, src: &str) -> Result<(), Error> {
+        let name = name.to_lowercase();
+
+        if self.is_executing(&name) {
+            return Err(Error::WordInUse);
         }
 
+        let body = tokenize(src);
+        self.check_stray_tokens(&body)?;
+        let compiled = Arc::new(self.compile(&body)?);
+
+        let mut source = vec![self.def_start.clone(), name.clone()];
+        source.extend(body);
+        source.push(self.def_end.clone());
+
+        let here = self.rt.mem.len();
+        self.dict
+            .insert(name, StdFuncSeq { inner: compiled }, &source, here);
+
         Ok(())
     }
 
+    /// Is there a user-defined word with the given name?
+    pub fn contains_word(&self, id: impl Into<WordId<String>>) -> bool {
+        let name = id.into().into_inner().to_lowercase();
+        self.dict.data.contains_key(&name)
+    }
+
+    /// Run a user-defined word to completion, using whatever is currently on
+    /// the data and return stacks as its arguments. If it fails, the stacks
+    /// are unwound
+    pub fn call_word(&mut self, id: impl Into<WordId<String>>) -> Result<(), Error> {
+        let name = id.into().into_inner().to_lowercase();
+        if !self.dict.data.contains_key(&name) {
+            return Err(Error::UnknownWord);
+        }
+
+        self.push_exec(RuntimeWord::VerbSeq(VerbSeqInner::from_word(name)))?;
+        self.run_or_unwind()
+    }
+
+    /// Call an optional hook word, such as `on-tick`, if the program defines
+    /// it. Returns the output produced (along with any output not yet taken),
+    /// or `None` if no such word is defined
+    pub fn try_call(&mut self, name: &str) -> Result<Option<String>, Error> {
+        if !self.contains_word(name) {
+            return Ok(None);
+        }
+
+        self.call_word(name)?;
+        Ok(Some(self.output()))
+    }
+
+    /// Rename a user-defined word, updating every sequence (and any retained
+    /// source) that refers to it, so that callers continue to work
+    pub fn rename_word(&mut self, old: &str, new: &str) -> Result<(), Error> {
+        let old = old.to_lowercase();
+        let new = new.to_lowercase();
+
+        if self.dict.data.contains_key(&new) || self.dict.bis.contains_key(&new) {
+            return Err(Error::WordExists);
+        }
+
+        if self.is_executing(&old) {
+            return Err(Error::WordInUse);
+        }
+
+        let seq = self.dict.data.remove(&old).ok_or(Error::UnknownWord)?;
+        self.dict.data.insert(new.clone(), seq);
+        for def in self.dict.order.iter_mut() {
+            if def.name == old {
+                def.name = new.clone();
+            }
+        }
+
+        for seq in self.dict.data.values_mut() {
+            let refers = seq.inner.iter().any(|word| match &word.word {
+                RuntimeWord::VerbSeq(vsi) => vsi.tok == old,
+                _ => false,
+            });
+
+            if refers {
+                let renamed = seq
+                    .inner
+                    .iter()
+                    .cloned()
+                    .map(|mut word| {
+                        if let RuntimeWord::VerbSeq(vsi) = &mut word.word {
+                            if vsi.tok == old {
+                                vsi.tok = new.clone();
+                                word.name = new.clone();
+                            }
+                        }
+                        word
+                    })
+                    .collect();
+                seq.inner = Arc::new(renamed);
+            }
+        }
+
+        if let Some(source) = self.dict.source.remove(&old) {
+            self.dict.source.insert(new.clone(), source);
+        }
+
+        for source in self.dict.source.values_mut() {
+            for tok in source.iter_mut().skip(1) {
+                if tok.to_lowercase() == old {
+                    *tok = new.clone();
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Remove a user-defined word, along with every word defined after it, as
+    /// with `forget` in standard Forth. If the word was redefined, only the
+    /// latest definition is removed, and the one it replaced is back in use.
+    /// Any memory allotted since the word was defined, such as for later
+    /// variables, is freed.
+    ///
+    /// This is rejected with `Error::WordInUse` if any of them are currently
+    /// executing, or are called by a word that would remain
+    pub fn forget(&mut self, name: &str) -> Result<(), Error> {
+        let name = name.to_lowercase();
+        let pos = match self.dict.order.iter().rposition(|def| def.name == name) {
+            Some(pos) => pos,
+            None => {
+                self.unknown_word = Some(name);
+                return Err(Error::UnknownWord);
+            }
+        };
+
+        let gone = &self.dict.order[pos..];
+        if gone.iter().any(|def| self.is_executing(&def.name)) {
+            return Err(Error::WordInUse);
+        }
+
+        let here = self.dict.forget(&name)?;
+        while self.rt.mem.len() > here {
+            self.rt.mem.pop()?;
+        }
+        Ok(())
+    }
+
+    fn is_def_start(&self, tok: &str) -> bool {
+        tok.eq_ignore_ascii_case(&self.def_start)
+    }
+
+    fn is_def_end(&self, tok: &str) -> bool {
+        tok.eq_ignore_ascii_case(&self.def_end)
+    }
+
+    /// Reject any definition start or end token in a line or definition body.
+    /// These are only valid as the first and last tokens of a definition,
+    /// which are removed before the body is checked. Strings, comments and
+    /// `char`s may contain either
+    fn check_stray_tokens(&self, data: &[String]) -> Result<(), Error> {
+        for (interp, tok) in interpreted_tokens(data).into_iter().zip(data) {
+            if !interp {
+                continue;
+            }
+            if self.is_def_end(tok) {
+                return Err(Error::UnexpectedSemicolon);
+            }
+            if self.is_def_start(tok) {
+                return Err(Error::UnexpectedColon);
+            }
+        }
+        Ok(())
+    }
+
+    /// Is the given sequence currently being executed?
+    fn is_executing(&self, name: &str) -> bool {
+        self.rt.flow_stk.data().iter().any(|frame| match frame {
+            RuntimeWord::VerbSeq(seq) => seq.tok == name,
+            _ => false,
+        })
+    }
+
     pub fn serialize(&self) -> SerDict {
         self.dict.serialize()
     }
 
+    pub fn serialize_to_writer<W: Write>(&self, out: &mut W) -> Result<(), Error> {
+        self.dict.serialize_to_writer(out)
+    }
+
     pub fn step(&mut self) -> Result<StepResult<BuiltinToken, String>, Error> {
         self.rt.step()
     }
 
+    /// Look up a sequence by token, including the sequences of evaluated bare
+    /// lines, which do not live in the `dict`
+    pub fn get_seq(&self, tok: &str) -> Option<&StdFuncSeq> {
+        if tok.starts_with(EPHEMERAL_PREFIX) {
+            self.ephemeral.get(tok)
+        } else {
+            self.dict.data.get(tok)
+        }
+    }
+
+    /// The names of the words currently being executed, from the outermost
+    /// call to the innermost. After a builtin fails, this shows where it was
+    /// called from. Failures within `step` itself unwind, leaving this empty
+    pub fn backtrace(&self) -> Vec<String> {
+        self.rt
+            .flow_stk
+            .data()
+            .iter()
+            .filter_map(|frame| match frame {
+                RuntimeWord::VerbSeq(seq) if !seq.tok.starts_with(EPHEMERAL_PREFIX) => {
+                    Some(seq.tok.clone())
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Look up the word referenced by a sequence step, for drivers that call
+    /// `step` themselves. `None` means the sequence has finished, and may be
+    /// passed directly to `provide_seq_tok`. No `dispatch_filter` is applied
+    pub fn resolve_seq(&self, rtw: &VerbSeqInner<String>) -> Option<StdRuntimeWord> {
+        self.get_seq(&rtw.tok)
+            .and_then(|n| n.inner.get(rtw.idx))
+            .map(|n| n.word.clone())
+    }
+
+    /// Look up the word referenced by a sequence step, applying the
+    /// `dispatch_filter` to builtins. A denied builtin unwinds all stacks
+    fn resolve(
+        &mut self,
+        rtw: &VerbSeqInner<String>,
+    ) -> Result<Option<NamedStdRuntimeWord>, Error> {
+        let word = match self.get_seq(&rtw.tok).and_then(|n| n.inner.get(rtw.idx)) {
+            Some(word) => word.clone(),
+            None => return Ok(None),
+        };
+
+        let filter = match (&word.word, self.dispatch_filter) {
+            (RuntimeWord::Verb(_), Some(filter)) => filter,
+            (RuntimeWord::Verb(_), None) => return self.allow_builtin(word),
+            _ => return Ok(Some(word)),
+        };
+
+        match filter(&word.name) {
+            Dispatch::Allow => self.allow_builtin(word),
+            Dispatch::Deny => {
+                self.rt.unwind();
+                Err(Error::BuiltinDenied)
+            }
+            Dispatch::Substitute(bi) => Ok(Some(NamedStdRuntimeWord {
+                name: word.name,
+                word: RuntimeWord::Verb(BuiltinToken::new(bi)),
+            })),
+        }
+    }
+
+    /// Apply `check_stack` to a builtin that is about to be executed
+    fn allow_builtin(
+        &mut self,
+        word: NamedStdRuntimeWord,
+    ) -> Result<Option<NamedStdRuntimeWord>, Error> {
+        if self.check_stack {
+            let needed = crate::builtins::arity(&word.name).map_or(0, |(inputs, _)| inputs);
+            if self.rt.data_stk.len() < needed {
+                self.underflow_word = Some(word.name);
+                return Err(Error::DataStackUnderflow);
+            }
+        }
+        Ok(Some(word))
+    }
+
+    /// Carry out the request made by the builtin that was just executed, for
+    /// builtins that need the dictionary, which the runtime can't see. Hosts
+    /// that execute builtins themselves should call this after each one
+    pub fn serve_host_request(&mut self) -> Result<(), Error> {
+        match self.rt.take_host_request() {
+            Some(HostRequest::ListWords) => {
+                let mut names = String::new();
+                for name in self.words() {
+                    names.push_str(name);
+                    names.push(' ');
+                }
+                core::fmt::Write::write_str(&mut self.rt.output(), &names)?;
+            }
+            None => {}
+        }
+        Ok(())
+    }
+
+    /// The names of every builtin and user-defined word, sorted. Builtins
+    /// used internally by control flow words are left out
+    pub fn words(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self
+            .dict
+            .bis
+            .keys()
+            .chain(self.dict.data.keys())
+            .map(String::as_str)
+            .filter(|name| !name.starts_with("PRIV_"))
+            .collect();
+        names.sort_unstable();
+        names.dedup();
+        names
+    }
+
+    /// The number of data stack items consumed and produced by a builtin, if
+    /// it is registered and has a fixed stack effect
+    pub fn builtin_arity(&self, name: &str) -> Option<(usize, usize)> {
+        let name = name.to_lowercase();
+        if !self.dict.bis.contains_key(&name) {
+            return None;
+        }
+        crate::builtins::arity(&name)
+    }
+
+    /// The names of all builtins and sequences directly referenced by the
+    /// given word, in order of first use. Unknown words have no dependencies
+    pub fn word_deps(&self, name: &str) -> Vec<String> {
+        let mut deps: Vec<String> = Vec::new();
+
+        for word in self.get_seq(name).iter().flat_map(|seq| seq.inner.iter()) {
+            let dep = match &word.word {
+                RuntimeWord::Verb(_) => &word.name,
+                RuntimeWord::VerbSeq(seq) => &seq.tok,
+                _ => continue,
+            };
+
+            if !deps.contains(dep) {
+                deps.push(dep.clone());
+            }
+        }
+
+        deps
+    }
+
+    /// A Graphviz DOT description of the call graph of all defined words.
+    /// Each sequence is a node, with an edge to every sequence it references.
+    /// Builtins are left out to keep the graph readable
+    pub fn to_dot(&self) -> String {
+        let mut out = String::from("digraph words {\n");
+
+        for name in self.dict.data.keys() {
+            out += &format!("    {};\n", dot_quote(name));
+        }
+
+        for name in self.dict.data.keys() {
+            for dep in self.word_deps(name) {
+                if self.dict.data.contains_key(&dep) {
+                    out += &format!("    {} -> {};\n", dot_quote(name), dot_quote(&dep));
+                }
+            }
+        }
+
+        out += "}\n";
+        out
+    }
+
+    /// Run until there is nothing left to execute. If a `key` needs input,
+    /// this fails with `Error::Input`, leaving the program paused: it may be
+    /// resumed by calling this again after `provide_input`.
+    ///
+    /// If a builtin fails, the stacks are left as they were when it failed, so
+    /// that `backtrace` and the data stack can be inspected. The caller must
+    /// then call `rt.unwind()` before evaluating anything else, or the next
+    /// run will carry on from the failed builtin. Failures within `step`
+    /// itself have already unwound
+    pub fn run_blocking(&mut self) -> Result<(), Error> {
+        // Frames left over from a run that ended in an error can't be closed
+        if let Some(prof) = self.profiler.as_mut() {
+            prof.frames.clear();
+        }
+
+        loop {
+            self.profile_leave();
+            match self.step()? {
+                StepResult::Working(WhichToken::Single(ft)) => {
+                    ft.exec(&mut self.rt)?;
+                    self.serve_host_request()?;
+                }
+                StepResult::Working(WhichToken::Ref(rtw)) => match self.resolve(&rtw)? {
+                    Some(word) => {
+                        self.rt.provide_seq_tok(Some(word.word))?;
+                        if let Some(prof) = self.profiler.as_mut() {
+                            prof.enter(word.name, self.rt.flow_stk.data().len());
+                        }
+                    }
+                    None => self.rt.provide_seq_tok(None)?,
+                },
+                StepResult::Done | StepResult::Quit | StepResult::Idle => break,
+                StepResult::NeedInput => return Err(Error::Input),
+            }
+        }
+        self.profile_leave();
+        Ok(())
+    }
+
+    /// As `run_blocking`, but unwinding after any failure other than waiting
+    /// for input, for callers that don't leave the failed program to be
+    /// inspected
+    fn run_or_unwind(&mut self) -> Result<(), Error> {
+        let res = self.run_blocking();
+        match res {
+            Ok(()) | Err(Error::Input) => {}
+            Err(_) => self.rt.unwind(),
+        }
+        res
+    }
+
+    fn profile_leave(&mut self) {
+        if let Some(prof) = self.profiler.as_mut() {
+            prof.leave_above(self.rt.flow_stk.data().len());
+        }
+    }
+
+    /// Run until the next observable event, resolving sequence references
+    /// and executing builtins internally. Branches that are not taken, and
+    /// returns from a sequence, do not produce an event
+    pub fn step_event(&mut self) -> Result<StepEvent, Error> {
+        if self.history_len > 0 {
+            if self.history.len() >= self.history_len {
+                self.history.pop_front();
+            }
+            self.history.push_back(Snapshot {
+                data_stk: self.rt.data_stk.clone(),
+                ret_stk: self.rt.ret_stk.clone(),
+                flow_stk: self.rt.flow_stk.clone(),
+            });
+        }
+
+        self.step_event_inner()
+    }
+
+    /// Undo the most recent `step_event`, restoring all stacks to how they
+    /// were before it. Output that was printed is not taken back. Returns
+    /// false if there is no more history to undo
+    pub fn step_back(&mut self) -> bool {
+        match self.history.pop_back() {
+            Some(snap) => {
+                self.rt.data_stk = snap.data_stk;
+                self.rt.ret_stk = snap.ret_stk;
+                self.rt.flow_stk = snap.flow_stk;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn step_event_inner(&mut self) -> Result<StepEvent, Error> {
+        loop {
+            let rtw = match self.rt.step_word()? {
+                Some(StepResult::Working(WhichToken::Ref(rtw))) => rtw,
+                Some(StepResult::Done) | Some(StepResult::Idle) => return Ok(StepEvent::Done),
+                Some(StepResult::Quit) => return Ok(StepEvent::Quit),
+                Some(StepResult::NeedInput) => return Ok(StepEvent::NeedInput),
+                _ => return Err(Error::InternalError),
+            };
+
+            let word = match self.resolve(&rtw)? {
+                Some(word) => word,
+                None => {
+                    self.rt.provide_seq_tok(None)?;
+                    continue;
+                }
+            };
+
+            self.rt.provide_seq_tok(Some(word.word.clone()))?;
+
+            match word.word {
+                RuntimeWord::VerbSeq(_) => {
+                    return Ok(StepEvent::EnteredSeq { name: word.name });
+                }
+                RuntimeWord::Verb(_) => match self.rt.step_word()? {
+                    Some(StepResult::Working(WhichToken::Single(ft))) => {
+                        ft.exec(&mut self.rt)?;
+                        self.serve_host_request()?;
+                        return Ok(StepEvent::ExecutedBuiltin { name: word.name });
+                    }
+                    _ => return Err(Error::InternalError),
+                },
+                RuntimeWord::LiteralVal(val) => {
+                    self.rt.step_word()?;
+                    return Ok(StepEvent::PushedLiteral { val });
+                }
+                RuntimeWord::UncondRelativeJump { .. } | RuntimeWord::CondRelativeJump { .. } => {
+                    self.rt.step_word()?;
+                    let to = self.rt.flow_stk.last_mut()?.as_seq_inner()?.idx;
+                    if to != rtw.idx + 1 {
+                        return Ok(StepEvent::Jumped { from: rtw.idx, to });
+                    }
+                }
+            }
+        }
+    }
+
     pub fn data_stack(&self) -> &StdVecStack<i32> {
         &self.rt.data_stk
     }
@@ -200,34 +1276,68 @@ impl Context {
         let mut new = Context {
             rt: new_runtime(),
             dict: Dict::new(),
+            compile_only: false,
+            def_start: ":".into(),
+            def_end: ";".into(),
+            dispatch_filter: None,
+            check_stack: false,
+            profiler: None,
+            history_len: 0,
+            history: VecDeque::new(),
+            conditions: Vec::new(),
+            ephemeral: BTreeMap::new(),
+            next_ephemeral: 0,
+            warnings: Vec::new(),
+            unknown_word: None,
+            underflow_word: None,
         };
 
-        for (word, func) in bi {
-            new.dict
-                .bis
-                .insert(word.to_string(), BuiltinToken::new(*func));
+        new.register_builtins(bi);
+        new
+    }
+
+    /// Add a builtin after construction, such as one provided by a plugin.
+    /// As with redefining a word, a builtin with the same name is replaced
+    /// with a warning. Words that are already compiled keep the old builtin
+    pub fn register_builtin(&mut self, name: &str, func: Builtin) {
+        if self.dict.bis.contains_key(name) {
+            self.warn(format!("redefined builtin `{}`", name));
         }
+        self.dict
+            .bis
+            .insert(name.to_string(), BuiltinToken::new(func));
+    }
 
-        new
+    pub fn register_builtins(&mut self, bi: &[(&'static str, Builtin)]) {
+        for (word, func) in bi {
+            self.register_builtin(word, *func);
+        }
     }
 
     pub fn output(&mut self) -> String {
         self.rt.exchange_output()
     }
 
-    pub fn push_exec(&mut self, word: StdRuntimeWord) {
-        self.rt.push_exec(word)
+    /// Warnings produced while evaluating, such as redefining a word, for a
+    /// host to show to the user
+    pub fn warnings(&self) -> &[Warning] {
+        &self.warnings
+    }
+
+    pub fn clear_warnings(&mut self) {
+        self.warnings.clear();
     }
-}
 
-// TODO: Expand number parser
-// Make this a function to later allow for more custom parsing
-// of literals like '0b1111_0000_1111_0000'
-//
-// See https://github.com/rust-analyzer/rust-analyzer/blob/c96481e25f08d1565cb9b3cac89323216e6f8d7f/crates/syntax/src/ast/token_ext.rs#L616-L662
-// for one way of doing this!
-fn parse_num(input: &str) -> Option<i32> {
-    input.parse::<i32>().ok()
+    fn warn(&mut self, message: impl Into<String>) {
+        self.warnings.push(Warning {
+            message: message.into(),
+            line: None,
+        });
+    }
+
+    pub fn push_exec(&mut self, word: StdRuntimeWord) -> Result<(), Error> {
+        self.rt.push_exec(word)
+    }
 }
 
 /// This struct represents a "chunk" of the AST
@@ -243,6 +1353,27 @@ enum Chunk {
     DoLoop {
         do_body: Vec<Chunk>,
     },
+    DoPlusLoop {
+        do_body: Vec<Chunk>,
+    },
+    BeginUntil {
+        body: Vec<Chunk>,
+    },
+    BeginWhileRepeat {
+        pre: Vec<Chunk>,
+        post: Vec<Chunk>,
+    },
+    Case {
+        /// The code leaving each `of` value, and the body run on a match
+        of_arms: Vec<(Vec<Chunk>, Vec<Chunk>)>,
+        default: Vec<Chunk>,
+    },
+    /// A `leave`, jumping out of the innermost enclosing `do` loop
+    Leave,
+    /// A value known while compiling, such as from `char`
+    Literal(i32),
+    /// The text of a `." ..."`, to be printed
+    PrintStr(String),
     Token(String),
     Comment {
         contents: Vec<String>,
@@ -250,18 +1381,50 @@ enum Chunk {
 }
 
 impl Chunk {
+    /// The first token, in source order, that is neither a builtin, a word
+    /// in the dictionary, nor a number
+    fn first_unknown<'a>(&'a self, dict: &Dict) -> Option<&'a str> {
+        match self {
+            Chunk::IfThen { if_body } => first_unknown(if_body, dict),
+            Chunk::IfElseThen { if_body, else_body } => {
+                first_unknown(if_body, dict).or_else(|| first_unknown(else_body, dict))
+            }
+            Chunk::DoLoop { do_body } | Chunk::DoPlusLoop { do_body } => {
+                first_unknown(do_body, dict)
+            }
+            Chunk::BeginUntil { body } => first_unknown(body, dict),
+            Chunk::BeginWhileRepeat { pre, post } => {
+                first_unknown(pre, dict).or_else(|| first_unknown(post, dict))
+            }
+            Chunk::Case { of_arms, default } => of_arms
+                .iter()
+                .find_map(|(value, body)| {
+                    first_unknown(value, dict).or_else(|| first_unknown(body, dict))
+                })
+                .or_else(|| first_unknown(default, dict)),
+            Chunk::Token(tok) => {
+                let known = dict.bis.contains_key(tok)
+                    || dict.data.contains_key(tok)
+                    || crate::parse_num(tok).is_some();
+                if known {
+                    None
+                } else {
+                    Some(tok.as_str())
+                }
+            }
+            Chunk::Leave | Chunk::Literal(_) | Chunk::PrintStr(_) | Chunk::Comment { .. } => None,
+        }
+    }
+
     /// Convert a chunk of AST words into a vec of `NamedStdRuntimeWord`s
-    fn to_named_rt_words(self, dict: &mut Dict) -> Vec<NamedStdRuntimeWord> {
+    fn to_named_rt_words(self, dict: &mut Dict) -> Result<Vec<NamedStdRuntimeWord>, Error> {
         let mut ret = vec![];
 
         match self {
             Chunk::IfThen { if_body } => {
                 // First, convert the body into a sequence
-                let mut conv: VecDeque<NamedStdRuntimeWord> = if_body
-                    .into_iter()
-                    .map(|m| m.to_named_rt_words(dict))
-                    .flatten()
-                    .collect();
+                let mut conv: VecDeque<NamedStdRuntimeWord> =
+                    chunks_to_named_rt_words(if_body, dict)?.into();
 
                 conv.push_front(NamedStdRuntimeWord {
                     name: "CRJ".into(),
@@ -275,17 +1438,10 @@ impl Chunk {
                 ret.extend(conv);
             }
             Chunk::IfElseThen { if_body, else_body } => {
-                let mut if_conv: VecDeque<NamedStdRuntimeWord> = if_body
-                    .into_iter()
-                    .map(|m| m.to_named_rt_words(dict))
-                    .flatten()
-                    .collect();
+                let mut if_conv: VecDeque<NamedStdRuntimeWord> =
+                    chunks_to_named_rt_words(if_body, dict)?.into();
 
-                let else_conv: Vec<NamedStdRuntimeWord> = else_body
-                    .into_iter()
-                    .map(|m| m.to_named_rt_words(dict))
-                    .flatten()
-                    .collect();
+                let else_conv = chunks_to_named_rt_words(else_body, dict)?;
 
                 if_conv.push_back(NamedStdRuntimeWord {
                     name: "UCRJ".into(),
@@ -307,42 +1463,152 @@ impl Chunk {
                 ret.extend(conv);
             }
             Chunk::DoLoop { do_body } => {
-                // First, convert the body into a sequence
-                let mut conv: VecDeque<NamedStdRuntimeWord> = do_body
-                    .into_iter()
-                    .map(|m| m.to_named_rt_words(dict))
-                    .flatten()
-                    .collect();
-
-                conv.push_back(NamedStdRuntimeWord {
+                let step = NamedStdRuntimeWord {
                     word: RuntimeWord::Verb(BuiltinToken::new(crate::builtins::bi_priv_loop)),
                     name: "PRIV_LOOP".into(),
-                });
+                };
+                ret.extend(do_loop_to_named_rt_words(do_body, step, dict)?);
+            }
+            Chunk::DoPlusLoop { do_body } => {
+                let step = NamedStdRuntimeWord {
+                    word: RuntimeWord::Verb(BuiltinToken::new(crate::builtins::bi_priv_plus_loop)),
+                    name: "PRIV_PLUS_LOOP".into(),
+                };
+                ret.extend(do_loop_to_named_rt_words(do_body, step, dict)?);
+            }
+            Chunk::BeginUntil { body } => {
+                let mut conv = chunks_to_named_rt_words(body, dict)?;
 
+                // Loop back to the top of the body while `until` sees a zero
+                // flag. The Minus One here accounts for the CRJ itself.
                 let len = conv.len();
-
-                conv.push_front(NamedStdRuntimeWord {
-                    word: RuntimeWord::Verb(BuiltinToken::new(crate::builtins::bi_retstk_push)),
-                    name: ">r".into(),
+                conv.push(NamedStdRuntimeWord {
+                    word: RuntimeWord::CondRelativeJump {
+                        offset: -1 * len as i32 - 1,
+                        jump_on: false,
+                    },
+                    name: "CRJ".into(),
                 });
-                conv.push_front(NamedStdRuntimeWord {
-                    word: RuntimeWord::Verb(BuiltinToken::new(crate::builtins::bi_retstk_push)),
-                    name: ">r".into(),
+
+                ret.extend(conv);
+            }
+            Chunk::BeginWhileRepeat { pre, post } => {
+                let pre_conv = chunks_to_named_rt_words(pre, dict)?;
+                let mut post_conv = chunks_to_named_rt_words(post, dict)?;
+
+                // Jump back to the top of `pre`. The Minus Two here accounts for
+                // the CRJ at `while` and this UCRJ itself.
+                post_conv.push(NamedStdRuntimeWord {
+                    name: "UCRJ".into(),
+                    word: RuntimeWord::UncondRelativeJump {
+                        offset: -1 * (pre_conv.len() + post_conv.len()) as i32 - 2,
+                    },
                 });
 
-                // The Minus One here accounts for the addition of the CRJ. We should not loop back to
-                // the double `>r`s, as those only happen once at the top of the loop.
-                conv.push_back(NamedStdRuntimeWord {
+                // At `while`, leave the loop (skipping `post` and the UCRJ)
+                // when the flag is zero
+                let mut conv = pre_conv;
+                conv.push(NamedStdRuntimeWord {
+                    name: "CRJ".into(),
                     word: RuntimeWord::CondRelativeJump {
-                        offset: -1 * len as i32 - 1,
+                        offset: post_conv.len() as i32,
                         jump_on: false,
                     },
-                    name: "CRJ".into(),
                 });
+                conv.extend(post_conv);
 
-                let conv: Vec<NamedStdRuntimeWord> = conv.into_iter().collect();
                 ret.extend(conv);
             }
+            Chunk::Case { of_arms, default } => {
+                // With no match, the default body runs and `endcase` drops
+                // the selector
+                let mut conv = chunks_to_named_rt_words(default, dict)?;
+                conv.push(NamedStdRuntimeWord {
+                    word: RuntimeWord::Verb(BuiltinToken::new(crate::builtins::bi_drop)),
+                    name: "drop".into(),
+                });
+
+                // Build each arm in front of those after it, so that every
+                // `endof` knows how far it is to the end of the `endcase`,
+                // skipping its final `drop`
+                for (value, body) in of_arms.into_iter().rev() {
+                    let mut arm = chunks_to_named_rt_words(value, dict)?;
+                    let body_conv = chunks_to_named_rt_words(body, dict)?;
+
+                    // `of` is `1 pick = if drop`, comparing a copy of the
+                    // selector against the value
+                    arm.push(NamedStdRuntimeWord {
+                        word: RuntimeWord::LiteralVal(1),
+                        name: "LIT(1)".into(),
+                    });
+                    arm.push(NamedStdRuntimeWord {
+                        word: RuntimeWord::Verb(BuiltinToken::new(crate::builtins::bi_pick)),
+                        name: "pick".into(),
+                    });
+                    arm.push(NamedStdRuntimeWord {
+                        word: RuntimeWord::Verb(BuiltinToken::new(crate::builtins::bi_eq)),
+                        name: "=".into(),
+                    });
+
+                    // The Plus Two here accounts for the `drop` and the UCRJ
+                    arm.push(NamedStdRuntimeWord {
+                        name: "CRJ".into(),
+                        word: RuntimeWord::CondRelativeJump {
+                            offset: body_conv.len() as i32 + 2,
+                            jump_on: false,
+                        },
+                    });
+                    arm.push(NamedStdRuntimeWord {
+                        word: RuntimeWord::Verb(BuiltinToken::new(crate::builtins::bi_drop)),
+                        name: "drop".into(),
+                    });
+                    arm.extend(body_conv);
+                    arm.push(NamedStdRuntimeWord {
+                        name: "UCRJ".into(),
+                        word: RuntimeWord::UncondRelativeJump {
+                            offset: conv.len() as i32,
+                        },
+                    });
+
+                    arm.extend(conv);
+                    conv = arm;
+                }
+
+                ret.extend(conv);
+            }
+            Chunk::Literal(val) => {
+                ret.push(NamedStdRuntimeWord {
+                    word: RuntimeWord::LiteralVal(val),
+                    name: format!("LIT({})", val),
+                });
+            }
+            Chunk::PrintStr(text) => {
+                for c in text.chars() {
+                    ret.push(NamedStdRuntimeWord {
+                        word: RuntimeWord::LiteralVal(c as i32),
+                        name: format!("LIT({})", c as i32),
+                    });
+                    ret.push(NamedStdRuntimeWord {
+                        word: RuntimeWord::Verb(BuiltinToken::new(crate::builtins::bi_emit)),
+                        name: "emit".into(),
+                    });
+                }
+            }
+            Chunk::Leave => {
+                // Drop the loop's index and limit, then jump past the end of
+                // the loop. The offset is only known once the whole loop has
+                // been converted, so it is patched by the enclosing loop
+                for _ in 0..2 {
+                    ret.push(NamedStdRuntimeWord {
+                        word: RuntimeWord::Verb(BuiltinToken::new(crate::builtins::bi_retstk_drop)),
+                        name: "rdrop".into(),
+                    });
+                }
+                ret.push(NamedStdRuntimeWord {
+                    word: RuntimeWord::UncondRelativeJump { offset: 0 },
+                    name: LEAVE_PLACEHOLDER.into(),
+                });
+            }
             Chunk::Token(tok) => {
                 ret.push(if let Some(bi) = dict.bis.get(&tok).cloned() {
                     NamedStdRuntimeWord {
@@ -354,14 +1620,13 @@ impl Chunk {
                         word: RuntimeWord::VerbSeq(VerbSeqInner::from_word(tok.clone())),
                         name: tok,
                     }
-                } else if let Some(num) = parse_num(&tok) {
+                } else if let Some(num) = crate::parse_num(&tok) {
                     NamedStdRuntimeWord {
                         word: RuntimeWord::LiteralVal(num),
                         name: format!("LIT({})", num),
                     }
                 } else {
-                    panic!("{:?}", tok);
-                    // return Err(Error::InternalError);
+                    return Err(Error::UnknownWord);
                 });
             }
             Chunk::Comment { .. } => {
@@ -369,39 +1634,278 @@ impl Chunk {
             }
         }
 
-        ret
+        Ok(ret)
+    }
+}
+
+/// The name given to the jump of a `leave` until its loop patches the offset.
+/// Tokens are lowercased, so no word can share this name
+const LEAVE_PLACEHOLDER: &str = "LEAVE";
+
+/// Convert the body of a counted loop, ending each pass with `step`, which
+/// leaves the flag for whether the loop is done
+fn do_loop_to_named_rt_words(
+    do_body: Vec<Chunk>,
+    step: NamedStdRuntimeWord,
+    dict: &mut Dict,
+) -> Result<Vec<NamedStdRuntimeWord>, Error> {
+    // First, convert the body into a sequence
+    let mut conv: VecDeque<NamedStdRuntimeWord> = chunks_to_named_rt_words(do_body, dict)?.into();
+
+    conv.push_back(step);
+
+    let len = conv.len();
+
+    conv.push_front(NamedStdRuntimeWord {
+        word: RuntimeWord::Verb(BuiltinToken::new(crate::builtins::bi_retstk_push)),
+        name: ">r".into(),
+    });
+    conv.push_front(NamedStdRuntimeWord {
+        word: RuntimeWord::Verb(BuiltinToken::new(crate::builtins::bi_retstk_push)),
+        name: ">r".into(),
+    });
+
+    // The Minus One here accounts for the addition of the CRJ. We should not loop back to
+    // the double `>r`s, as those only happen once at the top of the loop.
+    conv.push_back(NamedStdRuntimeWord {
+        word: RuntimeWord::CondRelativeJump {
+            offset: -1 * len as i32 - 1,
+            jump_on: false,
+        },
+        name: "CRJ".into(),
+    });
+
+    // Any `leave` not already claimed by an inner loop lands just past the CRJ
+    let total = conv.len();
+    for (pos, word) in conv.iter_mut().enumerate() {
+        if word.name == LEAVE_PLACEHOLDER {
+            word.name = "UCRJ".into();
+            word.word = RuntimeWord::UncondRelativeJump {
+                offset: (total - pos - 1) as i32,
+            };
+        }
+    }
+
+    Ok(conv.into_iter().collect())
+}
+
+fn chunks_to_named_rt_words(
+    chunks: Vec<Chunk>,
+    dict: &mut Dict,
+) -> Result<Vec<NamedStdRuntimeWord>, Error> {
+    let mut ret = vec![];
+    for chunk in chunks {
+        ret.extend(chunk.to_named_rt_words(dict)?);
     }
+    Ok(ret)
 }
 
 use std::collections::VecDeque;
 
-fn muncher(data: &mut VecDeque<String>) -> Vec<Chunk> {
-    let mut chunks = vec![];
-    loop {
-        let next = if let Some(t) = data.pop_front() {
-            t
-        } else {
-            break;
-        };
+fn first_unknown<'a>(chunks: &'a [Chunk], dict: &Dict) -> Option<&'a str> {
+    chunks.iter().find_map(|c| c.first_unknown(dict))
+}
 
-        match next.as_str() {
-            "do" => {
-                chunks.push(munch_do(data));
+/// Split a line of source into tokens on whitespace, as `evaluate` expects.
+/// The text of a `."` string is kept as a single token, so that its spacing
+/// is printed as written
+pub fn tokenize(line: &str) -> Vec<String> {
+    let mut out = Vec::new();
+    let mut scan = TokenScan::default();
+    let mut rest = line.trim_start();
+
+    while !rest.is_empty() {
+        let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        let (tok, after) = rest.split_at(end);
+        let starts_string = scan.interpreted(tok) && tok == ".\"";
+        out.push(tok.to_string());
+        rest = after;
+
+        if starts_string && !rest.is_empty() {
+            // Skip the single space separating the `."` from its text, which
+            // runs up to the first `"` that ends a word
+            let text = &rest[rest.chars().next().map_or(0, char::len_utf8)..];
+            let end = text
+                .match_indices('"')
+                .map(|(idx, _)| idx + 1)
+                .find(|&idx| idx == text.len() || text[idx..].starts_with(char::is_whitespace))
+                .unwrap_or(text.len());
+            if end > 0 {
+                scan.interpreted(&text[..end]);
+                out.push(text[..end].to_string());
             }
-            "if" => {
-                chunks.push(munch_if(data));
+            rest = &text[end..];
+        }
+        rest = rest.trim_start();
+    }
+
+    out
+}
+
+/// Tracks which tokens of a line are words to be interpreted, rather than the
+/// character after a `char`, or the text of a `."` string or `( )` comment,
+/// which may be anything at all
+#[derive(Default)]
+struct TokenScan {
+    in_string: bool,
+    after_char: bool,
+    comment_depth: usize,
+}
+
+impl TokenScan {
+    /// Is `tok`, the next token of the line, a word to be interpreted?
+    fn interpreted(&mut self, tok: &str) -> bool {
+        if self.in_string {
+            self.in_string = !tok.ends_with('"');
+            return false;
+        }
+        if self.after_char {
+            self.after_char = false;
+            return false;
+        }
+        if self.comment_depth > 0 {
+            match tok {
+                "(" => self.comment_depth += 1,
+                ")" => self.comment_depth -= 1,
+                _ => {}
             }
-            "(" => {
-                chunks.push(Chunk::Comment { contents: munch_comment(data) });
+            return false;
+        }
+
+        self.in_string = tok == ".\"";
+        self.after_char = tok.eq_ignore_ascii_case("char") || tok.eq_ignore_ascii_case("[char]");
+        if tok == "(" {
+            self.comment_depth = 1;
+        }
+        true
+    }
+}
+
+/// Whether each of the tokens of a line is a word to be interpreted
+fn interpreted_tokens(data: &[String]) -> Vec<bool> {
+    let mut scan = TokenScan::default();
+    data.iter().map(|tok| scan.interpreted(tok)).collect()
+}
+
+/// Drop a `\` and every token after it, unless it is part of a string,
+/// comment or `char`
+fn strip_line_comment(mut data: Vec<String>) -> Vec<String> {
+    let pos = interpreted_tokens(&data)
+        .into_iter()
+        .zip(&data)
+        .position(|(interp, t)| interp && t == "\\");
+    if let Some(pos) = pos {
+        data.truncate(pos);
+    }
+    data
+}
+
+/// Find the token that makes `compile_tokens` fail with `Error::UnknownWord`
+fn find_unknown_word(data: &[String], dict: &Dict) -> Option<String> {
+    let mut vd_data = lowercase_tokens(data);
+    let munched = muncher(&mut vd_data).ok()?;
+    first_unknown(&munched, dict).map(str::to_string)
+}
+
+/// Lowercase every token, except for those whose case matters: the
+/// character after a `char`, and the text of a `."` string or comment
+fn lowercase_tokens(data: &[String]) -> VecDeque<String> {
+    interpreted_tokens(data)
+        .into_iter()
+        .zip(data)
+        .map(|(interp, tok)| {
+            if interp {
+                tok.to_lowercase()
+            } else {
+                tok.clone()
             }
-            _ => chunks.push(Chunk::Token(next)),
+        })
+        .collect()
+}
+
+fn compile_tokens(data: &[String], dict: &mut Dict) -> Result<Vec<NamedStdRuntimeWord>, Error> {
+    let mut vd_data = lowercase_tokens(data);
+
+    let munched = muncher(&mut vd_data)?;
+    if !vd_data.is_empty() {
+        return Err(Error::InternalError);
+    }
+
+    let words = chunks_to_named_rt_words(munched, dict)?;
+
+    // A `leave` outside of any `do` loop has nowhere to go
+    if words.iter().any(|w| w.name == LEAVE_PLACEHOLDER) {
+        return Err(Error::UnbalancedControl);
+    }
+
+    Ok(words)
+}
+
+fn muncher(data: &mut VecDeque<String>) -> Result<Vec<Chunk>, Error> {
+    let mut chunks = vec![];
+    while let Some(next) = data.pop_front() {
+        chunks.push(munch_chunk(next, data)?);
+    }
+
+    Ok(chunks)
+}
+
+/// Munch the chunk starting at `next`, along with anything it owns: the body
+/// of a nested control structure, the character after a `char`, or the text
+/// of a string or comment. Each `munch_*` function checks for its own
+/// terminators before calling this, so any closing word that gets here is
+/// unbalanced
+fn munch_chunk(next: String, data: &mut VecDeque<String>) -> Result<Chunk, Error> {
+    match next.as_str() {
+        "do" => munch_do(data),
+        "if" => munch_if(data),
+        "case" => munch_case(data),
+        "begin" => munch_begin(data),
+        "leave" => Ok(Chunk::Leave),
+        "char" | "[char]" => munch_char(data),
+        ".\"" => Ok(Chunk::PrintStr(munch_string(data)?)),
+        "(" => Ok(Chunk::Comment {
+            contents: munch_comment(data)?,
+        }),
+        "then" | "else" | "loop" | "+loop" | "until" | "while" | "repeat" | "of" | "endof"
+        | "endcase" => Err(Error::UnbalancedControl),
+        _ => Ok(Chunk::Token(next)),
+    }
+}
+
+/// The codepoint of the single character token after a `char` or `[char]`.
+/// Unlike standard Forth, either may be used both inside and outside of a
+/// definition
+fn munch_char(data: &mut VecDeque<String>) -> Result<Chunk, Error> {
+    let tok = data.pop_front().ok_or(Error::BadCharLiteral)?;
+    let mut chars = tok.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Ok(Chunk::Literal(c as i32)),
+        _ => Err(Error::BadCharLiteral),
+    }
+}
+
+/// The text of a `."` string, up to the closing `"`. `tokenize` keeps this as
+/// one token, spacing and all, but if the line was split on whitespace some
+/// other way, the words are joined back together with single spaces
+fn munch_string(data: &mut VecDeque<String>) -> Result<String, Error> {
+    let mut text = String::new();
+    while let Some(tok) = data.pop_front() {
+        if !text.is_empty() {
+            text.push(' ');
+        }
+        if let Some(last) = tok.strip_suffix('"') {
+            text.push_str(last);
+            return Ok(text);
         }
+        text.push_str(&tok);
     }
 
-    chunks
+    // We never found our closing `"` after the `."`
+    Err(Error::UnterminatedString)
 }
 
-fn munch_comment(data: &mut VecDeque<String>) -> Vec<String> {
+fn munch_comment(data: &mut VecDeque<String>) -> Result<Vec<String>, Error> {
     let mut contents = vec![];
     loop {
         let next = if let Some(t) = data.pop_front() {
@@ -412,10 +1916,10 @@ fn munch_comment(data: &mut VecDeque<String>) -> Vec<String> {
 
         match next.as_str() {
             "(" => {
-                contents.extend(munch_comment(data));
+                contents.extend(munch_comment(data)?);
             }
             ")" => {
-                return contents;
+                return Ok(contents);
             }
             _ => {
                 contents.push(next);
@@ -423,89 +1927,115 @@ fn munch_comment(data: &mut VecDeque<String>) -> Vec<String> {
         }
     }
 
-    // We... shouldn't get here. This means we never found our ")" after the "("
-    todo!()
+    // We never found our ")" after the "("
+    Err(Error::UnterminatedComment)
 }
 
-fn munch_do(data: &mut VecDeque<String>) -> Chunk {
+fn munch_do(data: &mut VecDeque<String>) -> Result<Chunk, Error> {
     let mut chunks = vec![];
-    loop {
-        let next = if let Some(t) = data.pop_front() {
-            t
-        } else {
-            break;
-        };
+    while let Some(next) = data.pop_front() {
+        match next.as_str() {
+            "loop" => return Ok(Chunk::DoLoop { do_body: chunks }),
+            "+loop" => return Ok(Chunk::DoPlusLoop { do_body: chunks }),
+            _ => chunks.push(munch_chunk(next, data)?),
+        }
+    }
+
+    // We never found our "loop"/"+loop" after the "do"
+    Err(Error::MissingLoopPair)
+}
 
+fn munch_begin(data: &mut VecDeque<String>) -> Result<Chunk, Error> {
+    let mut chunks = vec![];
+    while let Some(next) = data.pop_front() {
         match next.as_str() {
-            "do" => {
-                chunks.push(munch_do(data));
-            }
-            "if" => {
-                chunks.push(munch_if(data));
-            }
-            "loop" => return Chunk::DoLoop { do_body: chunks },
-            _ => chunks.push(Chunk::Token(next)),
+            "until" => return Ok(Chunk::BeginUntil { body: chunks }),
+            "while" => return munch_while(data, chunks),
+            _ => chunks.push(munch_chunk(next, data)?),
         }
     }
 
-    // We... shouldn't get here. This means we never found our "loop" after the "do"
-    todo!()
+    // We never found our "until"/"while" after the "begin"
+    Err(Error::MissingUntilPair)
 }
 
-fn munch_if(data: &mut VecDeque<String>) -> Chunk {
+fn munch_while(data: &mut VecDeque<String>, pre: Vec<Chunk>) -> Result<Chunk, Error> {
     let mut chunks = vec![];
-    loop {
-        let next = if let Some(t) = data.pop_front() {
-            t
-        } else {
-            break;
-        };
+    while let Some(next) = data.pop_front() {
+        match next.as_str() {
+            "repeat" => return Ok(Chunk::BeginWhileRepeat { pre, post: chunks }),
+            _ => chunks.push(munch_chunk(next, data)?),
+        }
+    }
+
+    // We never found our "repeat" after the "while"
+    Err(Error::MissingRepeatPair)
+}
 
+fn munch_case(data: &mut VecDeque<String>) -> Result<Chunk, Error> {
+    let mut of_arms = vec![];
+    let mut chunks = vec![];
+    while let Some(next) = data.pop_front() {
         match next.as_str() {
-            "do" => {
-                chunks.push(munch_do(data));
+            "of" => {
+                let value = core::mem::take(&mut chunks);
+                of_arms.push((value, munch_of(data)?));
             }
-            "if" => {
-                chunks.push(munch_if(data));
-            }
-            "then" => return Chunk::IfThen { if_body: chunks },
-            "else" => {
-                return munch_else(data, chunks);
+            "endcase" => {
+                return Ok(Chunk::Case {
+                    of_arms,
+                    default: chunks,
+                })
             }
-            _ => chunks.push(Chunk::Token(next)),
+            _ => chunks.push(munch_chunk(next, data)?),
         }
     }
 
-    // We... shouldn't get here. This means we never found our "then"/"else" after the "if"
-    todo!()
+    // We never found our "endcase" after the "case"
+    Err(Error::MissingEndcasePair)
 }
 
-fn munch_else(data: &mut VecDeque<String>, if_body: Vec<Chunk>) -> Chunk {
+fn munch_of(data: &mut VecDeque<String>) -> Result<Vec<Chunk>, Error> {
     let mut chunks = vec![];
-    loop {
-        let next = if let Some(t) = data.pop_front() {
-            t
-        } else {
-            break;
-        };
+    while let Some(next) = data.pop_front() {
+        match next.as_str() {
+            "endof" => return Ok(chunks),
+            _ => chunks.push(munch_chunk(next, data)?),
+        }
+    }
+
+    // We never found our "endof" after the "of"
+    Err(Error::MissingEndofPair)
+}
 
+fn munch_if(data: &mut VecDeque<String>) -> Result<Chunk, Error> {
+    let mut chunks = vec![];
+    while let Some(next) = data.pop_front() {
+        match next.as_str() {
+            "then" => return Ok(Chunk::IfThen { if_body: chunks }),
+            "else" => return munch_else(data, chunks),
+            _ => chunks.push(munch_chunk(next, data)?),
+        }
+    }
+
+    // We never found our "then"/"else" after the "if"
+    Err(Error::MissingIfPair)
+}
+
+fn munch_else(data: &mut VecDeque<String>, if_body: Vec<Chunk>) -> Result<Chunk, Error> {
+    let mut chunks = vec![];
+    while let Some(next) = data.pop_front() {
         match next.as_str() {
-            "do" => {
-                chunks.push(munch_do(data));
-            }
-            "if" => {
-                chunks.push(munch_if(data));
-            }
             "then" => {
-                return Chunk::IfElseThen {
+                return Ok(Chunk::IfElseThen {
                     if_body,
                     else_body: chunks,
-                }
+                })
             }
-            _ => chunks.push(Chunk::Token(next)),
+            _ => chunks.push(munch_chunk(next, data)?),
         }
     }
 
-    // We... shouldn't get here. This means we never found our "then" after the "else"
-    todo!()
+    // We never found our "then" after the "else"
+    Err(Error::MissingElsePair)
 }
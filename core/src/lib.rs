@@ -90,9 +90,15 @@ pub enum Error {
     /// Flow/Execution stack was empty
     FlowStackEmpty,
 
+    /// Flow/Execution stack was full, usually due to runaway recursion
+    FlowStackOverflow,
+
     /// Some kind of checked math failed
     BadMath,
 
+    /// A division or remainder had a divisor of zero
+    DivideByZero,
+
     /// We found an "if" without an appropriate pair
     MissingIfPair,
 
@@ -105,6 +111,93 @@ pub enum Error {
     /// We found a "do" without an appropriate pair
     MissingDoPair,
 
+    /// We found a "begin" without an appropriate pair
+    MissingUntilPair,
+
+    /// We found a "while" without an appropriate pair
+    MissingRepeatPair,
+
+    /// We found a "case" without an appropriate pair
+    MissingEndcasePair,
+
+    /// We found an "of" without an appropriate pair
+    MissingEndofPair,
+
+    /// A non-definition line was evaluated in compile-only mode
+    NotADefinition,
+
+    /// The word is currently in use, and cannot be modified
+    WordInUse,
+
+    /// A serialized program has a missing or unsupported version header
+    UnsupportedVersion,
+
+    /// A definition was given where only an expression may be evaluated
+    NotAnExpression,
+
+    /// A `;` (or the configured definition end) was found outside of the end
+    /// of a definition
+    UnexpectedSemicolon,
+
+    /// A `:` (or the configured definition start) was found outside of the
+    /// start of a definition, or the definition was never closed
+    UnexpectedColon,
+
+    /// A definition was started with `:`, but no name was given
+    MissingWordName,
+
+    /// The word does not exist in the dictionary
+    UnknownWord,
+
+    /// A word with the given name already exists
+    WordExists,
+
+    /// A "then", "else", "loop", "until", "while", "repeat", "of", "endof",
+    /// or "endcase" was found without a matching "if", "do", "begin", or
+    /// "case", a "leave" outside of a "do" loop, or a "[then]" or "[else]"
+    /// without a matching "[if]"
+    UnbalancedControl,
+
+    /// We found a "(" without a matching ")"
+    UnterminatedComment,
+
+    /// A "char" or "[char]" was not followed by a single character
+    BadCharLiteral,
+
+    /// We found a `."` without a closing `"`
+    UnterminatedString,
+
+    /// A memory address was outside of the cells allocated by `variable` and
+    /// `allot`
+    BadAddress,
+
+    /// There is no room left to allocate memory cells
+    OutOfMemory,
+
+    /// The program called `abort`
+    Abort,
+
+    /// A builtin was denied by the dispatch filter
+    BuiltinDenied,
+
+    /// A loaded dictionary uses a builtin that this runtime doesn't have
+    MissingBuiltin,
+
+    /// A loaded program is malformed, such as a jump outside of its sequence
+    CorruptProgram,
+
+    /// More than `stall_limit` backward jumps were taken in a row without
+    /// any observable progress, so the program is likely stuck in a loop
+    LikelyInfiniteLoop,
+
+    /// A fixed-capacity buffer was too small, such as a name, sequence, or
+    /// dictionary in the `NoStdCompiler`
+    CapacityExceeded,
+
+    /// A branch was given a flag other than `0` or `true_value`, which is
+    /// only checked when `normalize_flags` is set
+    NonCanonicalFlag,
+
     /// Something has gone *terribly* wrong
     InternalError,
 }
@@ -137,8 +230,36 @@ impl<SeqTok> VerbSeqInner<SeqTok>
 where
     SeqTok: Clone,
 {
-    pub fn from_word(tok: SeqTok) -> Self {
-        Self { tok, idx: 0 }
+    pub fn from_word(id: impl Into<WordId<SeqTok>>) -> Self {
+        Self {
+            tok: id.into().0,
+            idx: 0,
+        }
+    }
+}
+
+/// Identifies a sequence: by name (`String`) in the std runtime, or by index
+/// (`usize`) in the no_std runtime. Any token converts into a `WordId`, as
+/// does a `&str` for the std runtime
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct WordId<SeqTok>(pub SeqTok);
+
+impl<SeqTok> WordId<SeqTok> {
+    pub fn into_inner(self) -> SeqTok {
+        self.0
+    }
+}
+
+impl<SeqTok> From<SeqTok> for WordId<SeqTok> {
+    fn from(tok: SeqTok) -> Self {
+        WordId(tok)
+    }
+}
+
+#[cfg(any(test, feature = "std"))]
+impl From<&str> for WordId<String> {
+    fn from(name: &str) -> Self {
+        WordId(name.to_string())
     }
 }
 
@@ -171,6 +292,48 @@ where
     }
 }
 
+/// The line ending written by `cr` and number printing words
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Newline {
+    /// Unix style, `\n`
+    Lf,
+
+    /// Serial terminal style, `\r\n`
+    CrLf,
+}
+
+impl Newline {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Newline::Lf => "\n",
+            Newline::CrLf => "\r\n",
+        }
+    }
+}
+
+/// How `emit` handles a value that is not a valid unicode code point, such as
+/// a surrogate or a negative number
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EmitMode {
+    /// Write `‽` in place of the invalid character
+    Lossy,
+
+    /// Fail with `Error::BadMath`
+    Strict,
+
+    /// Write only the low byte of the value, as the character with that code
+    /// point. Valid characters above `0xFF` are also truncated
+    RawByte,
+}
+
+/// Work asked of the host by a builtin, for things the runtime can't do
+/// itself. See `Runtime::take_host_request`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HostRequest {
+    /// Print the name of every word in the dictionary, for `words`
+    ListWords,
+}
+
 pub struct Runtime<BuiltinTok, SeqTok, Sdata, Sexec, O>
 where
     Sdata: Stack<Item = i32>,
@@ -182,8 +345,62 @@ where
     pub data_stk: Sdata,
     pub ret_stk: Sdata,
     pub flow_stk: Sexec,
+
+    /// Cells for mutable storage, addressed by index with `@` and `!`. Cells
+    /// are added by `variable` and `allot`, and are never freed. The contents
+    /// are not part of a serialized program
+    pub mem: Sdata,
     pub _pd_ty_t_f: PhantomData<(BuiltinTok, SeqTok)>,
+    pub newline: Newline,
+    pub emit_mode: EmitMode,
+
+    /// The flag pushed by comparison words when true. This is `-1` (all bits set)
+    /// by default, but may be set to `1` for interop with C-style booleans.
+    /// Branches treat any nonzero value as true, so either works with `if`
+    pub true_value: i32,
+
+    /// The base that `.` and `u.` print numbers in, set by `hex` and
+    /// `decimal`. Any base from 2 to 36 may be used. Number literals in
+    /// source are not affected
+    pub base: u32,
+
+    /// When set, branches (`if` and loops) only accept `0` or `true_value`
+    /// as a flag, and fail with `Error::NonCanonicalFlag` on anything else.
+    /// Builtins always push one of these, so this catches flags made by
+    /// arithmetic, or pushed by the host
+    pub normalize_flags: bool,
+
+    /// When set, an empty flow stack is reported as `StepResult::Idle` rather
+    /// than `Done`, for runtimes that live in an event loop and are given new
+    /// work (such as event handlers) as it arrives
+    pub idle_when_empty: bool,
+
+    /// When set, fail with `Error::LikelyInfiniteLoop` once more than this
+    /// many backward jumps are taken in a row without any change to the
+    /// depth or top two values of the data and return stacks, or to the
+    /// output. The top two cover the index of a `do` loop. This
+    /// catches a stuck loop long before a step budget would, though a loop
+    /// that keeps changing a value it never exits on still runs forever
+    pub stall_limit: Option<usize>,
     cur_output: O,
+    column: usize,
+    written: usize,
+    quitting: bool,
+    awaiting_input: bool,
+    next_key: Option<i32>,
+    host_request: Option<HostRequest>,
+    stalled_jumps: usize,
+    last_observed: Observed,
+}
+
+/// What `stall_limit` compares between backward jumps
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+struct Observed {
+    data_len: usize,
+    data_top: [Option<i32>; 2],
+    ret_len: usize,
+    ret_top: [Option<i32>; 2],
+    written: usize,
 }
 
 impl<Sdata, Sexec, BuiltinTok, SeqTok, O> Runtime<BuiltinTok, SeqTok, Sdata, Sexec, O>
@@ -198,97 +415,187 @@ where
         match self.step_inner() {
             Ok(r) => Ok(r),
             Err(e) => {
-                while self.flow_stk.pop().is_ok() {}
-                while self.data_stk.pop().is_ok() {}
-                while self.ret_stk.pop().is_ok() {}
+                self.unwind();
+                Err(e)
+            }
+        }
+    }
+
+    /// Empty all stacks, abandoning any execution in progress. This is done
+    /// automatically when `step` fails
+    pub fn unwind(&mut self) {
+        self.flow_stk.clear();
+        self.data_stk.clear();
+        self.ret_stk.clear();
+        self.quitting = false;
+        self.awaiting_input = false;
+        self.host_request = None;
+        self.stalled_jumps = 0;
+    }
+
+    /// Abandon any execution in progress, clearing the flow and return stacks
+    /// but keeping the data stack. The next step returns `StepResult::Quit`
+    pub fn quit(&mut self) {
+        self.flow_stk.clear();
+        self.ret_stk.clear();
+        self.quitting = true;
+    }
+
+    /// Like `step`, but processes at most a single word from the flow stack.
+    /// Returns `Ok(None)` if the word was a literal or jump, which the runtime
+    /// handles itself. This is useful for debuggers that want to observe every
+    /// change to the stacks, rather than only the points where work is yielded
+    pub fn step_word(&mut self) -> Result<Option<StepResult<BuiltinTok, SeqTok>>, Error> {
+        match self.step_word_inner() {
+            Ok(r) => Ok(r),
+            Err(e) => {
+                self.unwind();
                 Err(e)
             }
         }
     }
 
     fn step_inner(&mut self) -> Result<StepResult<BuiltinTok, SeqTok>, Error> {
-        let ret = 'oloop: loop {
-            // TODO: I should set a limit to the max number of loop
-            // iterations that are made here! Or maybe go back to
-            // yielding at each step
-            let cur = match self.flow_stk.last_mut() {
-                Ok(frame) => frame,
-                Err(_) => return Ok(StepResult::Done),
-            };
+        // TODO: I should set a limit to the max number of loop
+        // iterations that are made here! Or maybe go back to
+        // yielding at each step
+        loop {
+            if let Some(ret) = self.step_word_inner()? {
+                return Ok(ret);
+            }
+        }
+    }
 
-            let mut jump = None;
+    fn step_word_inner(&mut self) -> Result<Option<StepResult<BuiltinTok, SeqTok>>, Error> {
+        if self.awaiting_input {
+            return Ok(Some(StepResult::NeedInput));
+        }
 
-            let to_push = match cur {
-                RuntimeWord::LiteralVal(lit) => {
-                    self.data_stk.push(*lit)?;
-                    None
-                }
-                RuntimeWord::Verb(ft) => Some(WhichToken::Single(ft.clone())),
-                RuntimeWord::VerbSeq(ref mut seq) => {
-                    // TODO: I should probably check for a difference
-                    // between exactly one over-bounds (jump to end of seq),
-                    // and overshooting (probably an engine error)
-                    let ret = Some(WhichToken::Ref(seq.clone()));
-                    seq.idx += 1;
-                    ret
+        let cur = match self.flow_stk.last_mut() {
+            Ok(frame) => frame,
+            Err(_) => {
+                self.stalled_jumps = 0;
+                return Ok(Some(self.finished()));
+            }
+        };
+
+        let mut jump = None;
+
+        let to_push = match cur {
+            RuntimeWord::LiteralVal(lit) => {
+                self.data_stk.push(*lit)?;
+                None
+            }
+            RuntimeWord::Verb(ft) => Some(WhichToken::Single(ft.clone())),
+            RuntimeWord::VerbSeq(ref mut seq) => {
+                // TODO: I should probably check for a difference
+                // between exactly one over-bounds (jump to end of seq),
+                // and overshooting (probably an engine error)
+                let ret = Some(WhichToken::Ref(seq.clone()));
+                seq.idx += 1;
+                ret
+            }
+            RuntimeWord::UncondRelativeJump { offset } => {
+                jump = Some(*offset);
+                None
+            }
+            RuntimeWord::CondRelativeJump { offset, jump_on } => {
+                let topvar = self.data_stk.pop()?;
+                if self.normalize_flags && topvar != 0 && topvar != self.true_value {
+                    return Err(Error::NonCanonicalFlag);
                 }
-                RuntimeWord::UncondRelativeJump { offset } => {
+
+                // Truth table:
+                // tv == 0 | jump_on | jump
+                // ========|=========|=======
+                // false   | false   | no
+                // true    | false   | yes
+                // false   | true    | yes
+                // true    | true    | no
+                let do_jump = (topvar == 0) ^ *jump_on;
+                if do_jump {
                     jump = Some(*offset);
-                    None
                 }
-                RuntimeWord::CondRelativeJump { offset, jump_on } => {
-                    let topvar = self.data_stk.pop()?;
-
-                    // Truth table:
-                    // tv == 0 | jump_on | jump
-                    // ========|=========|=======
-                    // false   | false   | no
-                    // true    | false   | yes
-                    // false   | true    | yes
-                    // true    | true    | no
-                    let do_jump = (topvar == 0) ^ *jump_on;
-                    if do_jump {
-                        jump = Some(*offset);
-                    }
-
-                    None
-                }
-            };
 
-            match to_push {
-                Some(WhichToken::Single(ft)) => {
-                    self.flow_stk.pop()?;
-                    break 'oloop WhichToken::Single(ft);
-                }
-                Some(WhichToken::Ref(rf)) => {
-                    break 'oloop WhichToken::Ref(rf);
-                }
-                None => {
-                    self.flow_stk.pop()?;
-                }
+                None
             }
+        };
 
-            if let Some(jump) = jump {
-                // We just popped off the jump command, so now we are back in
-                // the "parent" frame.
+        match to_push {
+            Some(WhichToken::Single(ft)) => {
+                self.flow_stk.pop()?;
+                return Ok(Some(StepResult::Working(WhichToken::Single(ft))));
+            }
+            Some(WhichToken::Ref(rf)) => {
+                return Ok(Some(StepResult::Working(WhichToken::Ref(rf))));
+            }
+            None => {
+                self.flow_stk.pop()?;
+            }
+        }
 
-                let new_cur = self.flow_stk.last_mut()?.as_seq_inner()?;
+        if let Some(jump) = jump {
+            // We just popped off the jump command, so now we are back in
+            // the "parent" frame.
 
-                if jump < 0 {
-                    let abs = jump.abs() as usize;
+            let new_cur = self.flow_stk.last_mut()?.as_seq_inner()?;
 
-                    assert!(abs <= new_cur.idx);
+            // The compiler never produces a jump to before the start of a
+            // sequence, or a jump to itself, but a hand-crafted program might
+            let abs = jump.unsigned_abs() as usize;
+            new_cur.idx = match jump {
+                0 => return Err(Error::CorruptProgram),
+                j if j < 0 => new_cur.idx.checked_sub(abs).ok_or(Error::CorruptProgram)?,
+                _ => new_cur.idx.checked_add(abs).ok_or(Error::BadMath)?,
+            };
 
-                    new_cur.idx -= abs;
-                } else {
-                    let abs = jump as usize;
-                    assert_ne!(abs, 0);
-                    new_cur.idx = new_cur.idx.checked_add(abs).ok_or(Error::BadMath)?;
-                }
+            if jump < 0 {
+                self.check_stall()?;
             }
+        }
+
+        Ok(None)
+    }
+
+    /// The result of stepping with an empty flow stack
+    fn finished(&mut self) -> StepResult<BuiltinTok, SeqTok> {
+        if self.quitting {
+            self.quitting = false;
+            StepResult::Quit
+        } else if self.idle_when_empty {
+            StepResult::Idle
+        } else {
+            StepResult::Done
+        }
+    }
+
+    /// Count a backward jump towards the `stall_limit`, unless something
+    /// observable has changed since the last one
+    fn check_stall(&mut self) -> Result<(), Error> {
+        let limit = match self.stall_limit {
+            Some(limit) => limit,
+            None => return Ok(()),
         };
 
-        Ok(StepResult::Working(ret))
+        let now = Observed {
+            data_len: self.data_stk.len(),
+            data_top: [0, 1].map(|n| self.data_stk.peek_back(n).ok().copied()),
+            ret_len: self.ret_stk.len(),
+            ret_top: [0, 1].map(|n| self.ret_stk.peek_back(n).ok().copied()),
+            written: self.written,
+        };
+
+        if self.stalled_jumps > 0 && now == self.last_observed {
+            self.stalled_jumps += 1;
+        } else {
+            self.stalled_jumps = 1;
+            self.last_observed = now;
+        }
+
+        if self.stalled_jumps > limit {
+            return Err(Error::LikelyInfiniteLoop);
+        }
+        Ok(())
     }
 
     pub fn provide_seq_tok(
@@ -296,6 +603,11 @@ where
         seq: Option<RuntimeWord<BuiltinTok, SeqTok>>,
     ) -> Result<(), Error> {
         if let Some(mut word) = seq {
+            // The program can't continue, so abandon it as a failed step would
+            if self.flow_stk.is_full() {
+                self.unwind();
+                return Err(Error::FlowStackOverflow);
+            }
             if let Ok(wd) = word.as_seq_inner() {
                 assert_eq!(wd.idx, 0);
                 wd.idx = 0;
@@ -307,12 +619,115 @@ where
         Ok(())
     }
 
-    pub fn push_exec(&mut self, mut word: RuntimeWord<BuiltinTok, SeqTok>) {
+    /// The current column of the output, counted in characters since the
+    /// last newline. This is used for aligning printed tables
+    pub fn column(&self) -> usize {
+        self.column
+    }
+
+    /// The output buffer, for use by builtins. Anything written through this
+    /// keeps the `column` up to date
+    pub(crate) fn output(&mut self) -> ColumnWriter<'_, O> {
+        ColumnWriter {
+            out: &mut self.cur_output,
+            column: &mut self.column,
+            written: &mut self.written,
+        }
+    }
+
+    /// Convert a condition into a flag, using the configured `true_value`
+    pub fn flag(&self, cond: bool) -> i32 {
+        if cond {
+            self.true_value
+        } else {
+            0
+        }
+    }
+
+    /// Push a value to the data stack, e.g. to pass arguments to a word
+    pub fn push_data(&mut self, val: i32) -> Result<(), Error> {
+        self.data_stk.push(val)
+    }
+
+    /// Give a character to `key`. If a `key` is waiting (after
+    /// `StepResult::NeedInput`), it receives the character and execution may
+    /// continue. Otherwise, the character is held for the next `key`, and
+    /// fails with `Error::Input` if one is already held
+    pub fn provide_input(&mut self, ch: i32) -> Result<(), Error> {
+        if self.awaiting_input {
+            self.data_stk.push(ch)?;
+            self.awaiting_input = false;
+        } else if self.next_key.is_none() {
+            self.next_key = Some(ch);
+        } else {
+            return Err(Error::Input);
+        }
+        Ok(())
+    }
+
+    /// Ask the host to do something the runtime can't, on behalf of the
+    /// builtin being executed. Only the most recent request is kept
+    pub fn request_host(&mut self, req: HostRequest) {
+        self.host_request = Some(req);
+    }
+
+    /// Take the request made by the builtin that was just executed, if any.
+    /// Hosts that execute builtins themselves should check this after each
+    /// one. A request that is never taken is dropped when the stacks unwind
+    pub fn take_host_request(&mut self) -> Option<HostRequest> {
+        self.host_request.take()
+    }
+
+    /// Push each value to the data stack immediately, in order, so the last
+    /// one ends up on top. Pushing a `RuntimeWord::LiteralVal` with `push_exec`
+    /// instead only reaches the data stack when it is stepped over, and in
+    /// the opposite order, as the flow stack runs the last push first
+    pub fn push_literals(&mut self, vals: &[i32]) -> Result<(), Error> {
+        for val in vals {
+            self.data_stk.push(*val)?;
+        }
+        Ok(())
+    }
+
+    /// Pop a value from the data stack, e.g. to read the result of a word
+    pub fn pop_data(&mut self) -> Result<i32, Error> {
+        self.data_stk.pop()
+    }
+
+    /// Push a value to the return stack, e.g. to set up a call convention
+    /// that the invoked word consumes with `r>`
+    pub fn push_return(&mut self, val: i32) -> Result<(), Error> {
+        self.ret_stk.push(val)
+    }
+
+    /// Pop a value from the return stack
+    pub fn pop_return(&mut self) -> Result<i32, Error> {
+        self.ret_stk.pop()
+    }
+
+    /// The data stack, for host code that needs more than `push_data` and
+    /// `pop_data`, such as injecting a result after a yield. Anything left on
+    /// the stack must be what the running program expects to find there
+    pub fn data_stack_mut(&mut self) -> &mut Sdata {
+        &mut self.data_stk
+    }
+
+    /// The return stack. As with `data_stack_mut`, the caller must not
+    /// disturb any loop counters or other values the program has stored here
+    pub fn return_stack_mut(&mut self) -> &mut Sdata {
+        &mut self.ret_stk
+    }
+
+    pub fn push_exec(&mut self, mut word: RuntimeWord<BuiltinTok, SeqTok>) -> Result<(), Error> {
+        if self.flow_stk.is_full() {
+            return Err(Error::FlowStackOverflow);
+        }
         if let Ok(wd) = word.as_seq_inner() {
             assert_eq!(wd.idx, 0);
             wd.idx = 0;
         }
         self.flow_stk.push(word);
+        Ok(())
     }
 }
 
@@ -331,6 +746,70 @@ where
     }
 }
 
+impl<Sdata, Sexec, BuiltinTok, SeqTok, O> Runtime<BuiltinTok, SeqTok, Sdata, Sexec, O>
+where
+    Sdata: Stack<Item = i32>,
+    Sexec: ExecutionStack<BuiltinTok, SeqTok>,
+    SeqTok: Clone,
+    BuiltinTok: Clone,
+    O: Write + Default + AsRef<str>,
+{
+    /// Drain any pending output into the given sink. Nothing is sent to the sink
+    /// if there is no pending output
+    pub fn flush_output<S: OutputSink>(&mut self, sink: &mut S) -> Result<(), Error> {
+        let output = self.exchange_output();
+        match output.as_ref() {
+            "" => Ok(()),
+            out => sink.flush(out),
+        }
+    }
+}
+
+impl<Sdata, Sexec, BuiltinTok, SeqTok, O> Runtime<BuiltinTok, SeqTok, Sdata, Sexec, O>
+where
+    Sdata: Stack<Item = i32>,
+    Sexec: ExecutionStack<BuiltinTok, SeqTok>,
+    SeqTok: Clone,
+    BuiltinTok: Clone,
+    O: Write + OutputBuffer,
+{
+    /// Discard any pending output without reading it, e.g. after a failed
+    /// line. Unlike `exchange_output`, the existing buffer is reused
+    pub fn clear_output(&mut self) {
+        self.cur_output.clear();
+    }
+}
+
+/// Forwards writes to an output buffer, tracking the current column
+pub(crate) struct ColumnWriter<'a, O: Write> {
+    out: &'a mut O,
+    column: &'a mut usize,
+    written: &'a mut usize,
+}
+
+impl<'a, O: Write> Write for ColumnWriter<'a, O> {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        for ch in s.chars() {
+            match ch {
+                '\n' | '\r' => *self.column = 0,
+                _ => *self.column += 1,
+            }
+            *self.written = self.written.wrapping_add(1);
+        }
+        self.out.write_str(s)
+    }
+}
+
+/// A destination for output drained from a `Runtime`, such as a UART or a logger
+pub trait OutputSink {
+    fn flush(&mut self, output: &str) -> Result<(), Error>;
+}
+
+/// An output buffer that can be emptied in place, keeping its storage
+pub trait OutputBuffer {
+    fn clear(&mut self);
+}
+
 pub trait Stack {
     type Item;
 
@@ -338,9 +817,42 @@ pub trait Stack {
     fn pop(&mut self) -> Result<Self::Item, Error>;
     fn peek_back(&self, back: usize) -> Result<&Self::Item, Error>;
     fn pop_back(&mut self, back: usize) -> Result<Self::Item, Error>;
+    fn len(&self) -> usize;
+    fn clear(&mut self);
+
+    /// The whole stack, bottom first
+    fn as_slice(&self) -> &[Self::Item];
+    fn as_mut_slice(&mut self) -> &mut [Self::Item];
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
 
     // Needed for builtins
     fn last(&self) -> Result<&Self::Item, Error>;
+
+    /// Pop the top two items, returned in stack order: `( a b -- )` gives
+    /// `[a, b]`. The stack is left untouched if it holds fewer than two items,
+    /// so a builtin never fails part way through consuming its arguments
+    fn pop2(&mut self) -> Result<[Self::Item; 2], Error> {
+        if self.len() < 2 {
+            return Err(Error::DataStackUnderflow);
+        }
+        let b = self.pop()?;
+        let a = self.pop()?;
+        Ok([a, b])
+    }
+
+    /// Pop the top three items, returned in stack order: `( a b c -- )` gives
+    /// `[a, b, c]`. The stack is left untouched if it holds fewer than three items
+    fn pop3(&mut self) -> Result<[Self::Item; 3], Error> {
+        if self.len() < 3 {
+            return Err(Error::DataStackUnderflow);
+        }
+        let c = self.pop()?;
+        let [a, b] = self.pop2()?;
+        Ok([a, b, c])
+    }
 }
 
 pub trait ExecutionStack<BuiltinTok, SeqTok>
@@ -351,6 +863,10 @@ where
     fn push(&mut self, data: RuntimeWord<BuiltinTok, SeqTok>);
     fn pop(&mut self) -> Result<RuntimeWord<BuiltinTok, SeqTok>, Error>;
     fn last_mut(&mut self) -> Result<&mut RuntimeWord<BuiltinTok, SeqTok>, Error>;
+    fn clear(&mut self);
+
+    /// Would another `push` exceed the capacity of the stack?
+    fn is_full(&self) -> bool;
 }
 
 pub enum StepResult<BuiltinTok, SeqTok>
@@ -359,9 +875,68 @@ where
     BuiltinTok: Clone,
 {
     Done,
+
+    /// Execution was stopped early by `quit`, leaving the data stack intact
+    Quit,
+
+    /// There is nothing left to execute, but more work is expected to be
+    /// pushed. Returned instead of `Done` when `idle_when_empty` is set
+    Idle,
+
+    /// A `key` is waiting for a character, which the host must give with
+    /// `Runtime::provide_input` before execution can continue
+    NeedInput,
     Working(WhichToken<BuiltinTok, SeqTok>),
 }
 
+/// Parse a number literal, in decimal, in hex with a `0x` or `$` prefix, or
+/// in binary with a `0b` or `%` prefix, each with an optional leading `-`.
+/// Prefixed literals may use all 32 bits, so `0xffffffff` is `-1`, but a
+/// negated one must not be larger than `0x80000000`. Digits may be grouped
+/// with single `_` separators, as in `1_000_000`, but not start or end with
+/// one. This doesn't allocate, so both compilers accept the same literals
+pub(crate) fn parse_num(input: &str) -> Option<i32> {
+    let (neg, unsigned) = match input.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, input),
+    };
+    if unsigned.starts_with('_') || unsigned.ends_with('_') || unsigned.contains("__") {
+        return None;
+    }
+
+    let (radix, digits) = if let Some(hex) = unsigned
+        .strip_prefix("0x")
+        .or_else(|| unsigned.strip_prefix('$'))
+    {
+        (16, hex)
+    } else if let Some(bin) = unsigned
+        .strip_prefix("0b")
+        .or_else(|| unsigned.strip_prefix('%'))
+    {
+        (2, bin)
+    } else if neg {
+        (10, unsigned)
+    } else {
+        (10, unsigned.strip_prefix('+').unwrap_or(unsigned))
+    };
+
+    if digits.is_empty() {
+        return None;
+    }
+    let mut magnitude: u32 = 0;
+    for ch in digits.chars().filter(|&ch| ch != '_') {
+        let digit = ch.to_digit(radix)?;
+        magnitude = magnitude.checked_mul(radix)?.checked_add(digit)?;
+    }
+
+    match (neg, magnitude) {
+        (false, mag) if radix == 10 => i32::try_from(mag).ok(),
+        (false, mag) => Some(mag as i32),
+        (true, mag) if mag <= 0x8000_0000 => Some((mag as i32).wrapping_neg()),
+        (true, _) => None,
+    }
+}
+
 #[cfg(test)]
 mod std_test {
     use super::*;
@@ -434,11 +1009,12 @@ mod std_test {
         // // treating it as an "entry point"
         x.push_exec(RuntimeWord::VerbSeq(VerbSeqInner::from_word(
             "mstar".to_string(),
-        )));
+        )))
+        .unwrap();
 
         loop {
             match x.step() {
-                Ok(StepResult::Done) => break,
+                Ok(StepResult::Done) | Ok(StepResult::Quit) | Ok(StepResult::Idle) => break,
                 Ok(StepResult::Working(WhichToken::Single(ft))) => {
                     // The runtime yields back at every call to a "builtin". Here, I
                     // call the builtin immediately, but I could also yield further up,
@@ -457,6 +1033,7 @@ mod std_test {
 
                     x.provide_seq_tok(c).unwrap();
                 }
+                Ok(StepResult::NeedInput) => unreachable!("no input words used"),
                 Err(_e) => todo!(),
             }
         }
@@ -465,6 +1042,115 @@ mod std_test {
 
         assert_eq!("***", &output);
     }
+
+    #[test]
+    fn idle_when_empty() {
+        let mut x = new_runtime();
+        x.idle_when_empty = true;
+
+        for ch in ['o', 'k'] {
+            x.push_exec(RuntimeWord::Verb(BuiltinToken::new(builtins::bi_emit)))
+                .unwrap();
+            x.push_exec(RuntimeWord::LiteralVal(ch as i32)).unwrap();
+            loop {
+                match x.step().unwrap() {
+                    StepResult::Working(WhichToken::Single(ft)) => ft.exec(&mut x).unwrap(),
+                    StepResult::Idle => break,
+                    _ => panic!(),
+                }
+            }
+        }
+        assert_eq!("ok", &x.exchange_output());
+
+        x.idle_when_empty = false;
+        assert!(matches!(x.step(), Ok(StepResult::Done)));
+    }
+
+    #[test]
+    fn binops() {
+        type Op = fn(&mut StdRuntime) -> Result<(), Error>;
+        type Reference = fn(i32, i32) -> i32;
+        let ops: [(Op, Reference); 3] = [
+            (builtins::bi_add, i32::wrapping_add),
+            (builtins::bi_sub, i32::wrapping_sub),
+            (builtins::bi_mul, i32::wrapping_mul),
+        ];
+        let vals = [0, 1, -1, 7, -300, i32::MAX, i32::MIN];
+
+        let mut x = new_runtime();
+        for (op, expected) in ops.iter() {
+            for a in vals.iter() {
+                for b in vals.iter() {
+                    x.data_stk.push(*a).unwrap();
+                    x.data_stk.push(*b).unwrap();
+                    op(&mut x).unwrap();
+                    assert_eq!(x.data_stk.pop().unwrap(), expected(*a, *b));
+                    assert!(x.data_stk.is_empty());
+                }
+            }
+
+            // Nothing is consumed on underflow
+            x.data_stk.push(1).unwrap();
+            assert!(matches!(op(&mut x), Err(Error::DataStackUnderflow)));
+            assert_eq!(x.data_stk.pop().unwrap(), 1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod unwind_test {
+    use super::*;
+
+    fn unwind<BuiltinTok, SeqTok, Sdata, Sexec, O>(
+        rt: &mut Runtime<BuiltinTok, SeqTok, Sdata, Sexec, O>,
+    ) where
+        Sdata: Stack<Item = i32>,
+        Sexec: ExecutionStack<BuiltinTok, SeqTok>,
+        SeqTok: Clone,
+        BuiltinTok: Clone,
+        O: Write,
+    {
+        rt.data_stk.push(1).unwrap();
+        rt.data_stk.push(2).unwrap();
+        rt.ret_stk.push(3).unwrap();
+
+        // A jump must return to a sequence, not a literal
+        rt.push_exec(RuntimeWord::LiteralVal(4)).unwrap();
+        rt.push_exec(RuntimeWord::UncondRelativeJump { offset: 1 })
+            .unwrap();
+        assert!(matches!(rt.step(), Err(Error::InternalError)));
+
+        assert!(rt.data_stk.is_empty());
+        assert!(rt.ret_stk.is_empty());
+        assert!(matches!(rt.step(), Ok(StepResult::Done)));
+    }
+
+    #[test]
+    fn std_unwind() {
+        unwind(&mut std_rt::new_runtime());
+    }
+
+    #[test]
+    fn bad_jumps() {
+        let mut rt = std_rt::new_runtime();
+
+        for offset in [-5, 0, i32::MIN] {
+            rt.data_stk.push(1).unwrap();
+            rt.push_exec(RuntimeWord::VerbSeq(VerbSeqInner::from_word("x")))
+                .unwrap();
+            rt.push_exec(RuntimeWord::UncondRelativeJump { offset })
+                .unwrap();
+            assert!(matches!(rt.step(), Err(Error::CorruptProgram)));
+
+            assert!(rt.data_stk.is_empty());
+            assert!(matches!(rt.step(), Ok(StepResult::Done)));
+        }
+    }
+
+    #[test]
+    fn nostd_unwind() {
+        unwind(&mut nostd_rt::new_runtime::<8, 8, 8>());
+    }
 }
 
 #[cfg(test)]
@@ -549,11 +1235,12 @@ mod nostd_test {
         x.push_exec(RuntimeWord::VerbSeq(
             // Insert `mstar`, which is deser_dict[1]
             VerbSeqInner { tok: 1, idx: 0 },
-        ));
+        ))
+        .unwrap();
 
         loop {
             match x.step() {
-                Ok(StepResult::Done) => {
+                Ok(StepResult::Done) | Ok(StepResult::Quit) | Ok(StepResult::Idle) => {
                     break;
                 }
                 Ok(StepResult::Working(WhichToken::Single(ft))) => {
@@ -574,6 +1261,7 @@ mod nostd_test {
 
                     x.provide_seq_tok(c).unwrap();
                 }
+                Ok(StepResult::NeedInput) => unreachable!("no input words used"),
                 Err(_e) => todo!(),
             }
         }
@@ -582,4 +1270,179 @@ mod nostd_test {
 
         assert_eq!("***", &output);
     }
+
+    #[test]
+    fn nostd_compiler() {
+        let mut ctxt: NoStdContext<8, 8, 8, 4, 8> = NoStdContext {
+            rt: new_runtime(),
+            seq: Vec::new(),
+        };
+        let mut comp: NoStdCompiler<4, 8> = NoStdCompiler::new();
+
+        let star = comp.compile_line(&mut ctxt, ": star 42 emit ;").unwrap();
+        let stars = comp.compile_line(&mut ctxt, ": STARS star star ;").unwrap();
+        assert_eq!(Some(star), comp.lookup("STAR"));
+        assert_eq!(Some(stars), comp.lookup("stars"));
+
+        ctxt.rt
+            .push_exec(RuntimeWord::VerbSeq(VerbSeqInner::from_word(star)))
+            .unwrap();
+        ctxt.run_blocking().unwrap();
+        assert_eq!("*", &ctxt.rt.exchange_output());
+
+        ctxt.rt
+            .push_exec(RuntimeWord::VerbSeq(VerbSeqInner::from_word(stars)))
+            .unwrap();
+        ctxt.run_blocking().unwrap();
+        assert_eq!("**", &ctxt.rt.exchange_output());
+
+        // Nothing is stored on failure
+        let err = comp.compile_line(&mut ctxt, ": bad nope ;");
+        assert!(matches!(err, Err(Error::UnknownWord)));
+        let err = comp.compile_line(&mut ctxt, ": star 43 emit ;");
+        assert!(matches!(err, Err(Error::WordExists)));
+        let err = comp.compile_line(&mut ctxt, ": toolongname ;");
+        assert!(matches!(err, Err(Error::CapacityExceeded)));
+        let err = comp.compile_line(&mut ctxt, "42 emit");
+        assert!(matches!(err, Err(Error::NotADefinition)));
+        assert_eq!(2, ctxt.seq.len());
+        assert_eq!(None, comp.lookup("bad"));
+    }
+
+    #[test]
+    fn nostd_literals() {
+        let mut ctxt: NoStdContext<8, 8, 8, 4, 8> = NoStdContext {
+            rt: new_runtime(),
+            seq: Vec::new(),
+        };
+        let mut comp: NoStdCompiler<4, 8> = NoStdCompiler::new();
+
+        // The same literals as the std compiler accepts
+        let src = ": lits 0x10 $FF %101 -0b11 1_000 -0x80000000 ;";
+        let lits = comp.compile_line(&mut ctxt, src).unwrap();
+        ctxt.rt
+            .push_exec(RuntimeWord::VerbSeq(VerbSeqInner::from_word(lits)))
+            .unwrap();
+        ctxt.run_blocking().unwrap();
+        assert_eq!(
+            &[16, 255, 5, -3, 1000, i32::MIN],
+            ctxt.rt.data_stk.as_slice()
+        );
+
+        let err = comp.compile_line(&mut ctxt, ": bad 1__0 ;");
+        assert!(matches!(err, Err(Error::UnknownWord)));
+    }
+
+    #[test]
+    fn builtin_tables_match() {
+        // A program compiled on the host may use any std builtin
+        let std_names = crate::std_rt::std_builtins().iter().map(|(name, _)| *name);
+        let nostd_names = nostd_builtins::<8, 8, 8>().iter().map(|(name, _)| *name);
+        assert!(std_names.eq(nostd_names));
+    }
+
+    #[test]
+    fn nostd_allot() {
+        let mut ctxt: NoStdContext<8, 8, 8, 4, 8> = NoStdContext {
+            rt: new_runtime(),
+            seq: Vec::new(),
+        };
+        let mut comp: NoStdCompiler<4, 8> = NoStdCompiler::new();
+        let some = comp.compile_line(&mut ctxt, ": some 6 allot ;").unwrap();
+
+        ctxt.rt
+            .push_exec(RuntimeWord::VerbSeq(VerbSeqInner::from_word(some)))
+            .unwrap();
+        ctxt.run_blocking().unwrap();
+
+        ctxt.rt
+            .push_exec(RuntimeWord::VerbSeq(VerbSeqInner::from_word(some)))
+            .unwrap();
+        let err = ctxt.run_blocking();
+        assert!(matches!(err, Err(Error::OutOfMemory)));
+
+        // The second allot didn't fit, and allocated nothing
+        assert_eq!(6, ctxt.rt.mem.len());
+    }
+
+    struct MockSink(std::vec::Vec<std::string::String>);
+
+    impl OutputSink for MockSink {
+        fn flush(&mut self, output: &str) -> Result<(), Error> {
+            self.0.push(output.into());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn flush_to_sink() {
+        let mut x = new_runtime::<8, 8, 8>();
+        let mut sink = MockSink(std::vec::Vec::new());
+
+        for ch in [b'o', b'k'] {
+            x.push_exec(RuntimeWord::Verb(BuiltinToken::new(builtins::bi_emit)))
+                .unwrap();
+            x.push_exec(RuntimeWord::LiteralVal(ch.into())).unwrap();
+            while let Ok(StepResult::Working(WhichToken::Single(ft))) = x.step() {
+                ft.exec(&mut x).unwrap();
+            }
+            x.flush_output(&mut sink).unwrap();
+        }
+
+        // Nothing pending, nothing flushed
+        x.flush_output(&mut sink).unwrap();
+        assert_eq!(vec!["o", "k"], sink.0);
+        assert_eq!("", &x.exchange_output());
+    }
+
+    #[test]
+    fn flow_stack_overflow() {
+        let mut ctxt: NoStdContext<8, 4, 8, 1, 4> = NoStdContext {
+            rt: new_runtime(),
+            seq: Vec::new(),
+        };
+
+        // A word that calls itself forever, roughly:
+        // : forever forever ;
+        let mut word = Vec::new();
+        word.push(RuntimeWord::VerbSeq(VerbSeqInner::from_word(0)))
+            .ok();
+        ctxt.seq.push(word).ok();
+
+        ctxt.rt
+            .push_exec(RuntimeWord::VerbSeq(VerbSeqInner::from_word(0)))
+            .unwrap();
+        assert!(matches!(ctxt.run_blocking(), Err(Error::FlowStackOverflow)));
+        assert!(matches!(ctxt.rt.step(), Ok(StepResult::Done)));
+
+        for _ in 0..4 {
+            ctxt.rt.push_exec(RuntimeWord::LiteralVal(1)).unwrap();
+        }
+        let res = ctxt.rt.push_exec(RuntimeWord::LiteralVal(1));
+        assert!(matches!(res, Err(Error::FlowStackOverflow)));
+    }
+
+    #[test]
+    fn missing_builtin() {
+        use crate::ser_de::{SerDictFixed, SerWord};
+
+        let mut word: Vec<SerWord, 4> = Vec::new();
+        word.push(SerWord::LiteralVal(42)).ok();
+        word.push(SerWord::Verb(1)).ok();
+
+        let mut dict: SerDictFixed<1, 4, 2> = SerDictFixed {
+            data: Vec::new(),
+            data_map: None,
+            bis: Vec::new(),
+        };
+        dict.data.push(word).ok();
+        dict.bis.push("emit").ok();
+        dict.bis.push("frobnicate").ok();
+
+        let res = NoStdContext::<8, 8, 8, 1, 4>::from_ser_dict(&dict);
+        assert_eq!(res.err(), Some(MissingBuiltin("frobnicate")));
+
+        dict.bis[1] = "emit";
+        assert!(NoStdContext::<8, 8, 8, 1, 4>::from_ser_dict(&dict).is_ok());
+    }
 }
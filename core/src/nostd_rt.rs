@@ -2,6 +2,9 @@ use core::marker::PhantomData;
 
 use crate::ser_de::SerDictFixed;
 use crate::ser_de::SerWord;
+use crate::EmitMode;
+use crate::Newline;
+use crate::OutputBuffer;
 use crate::Runtime;
 use crate::RuntimeWord;
 use crate::StepResult;
@@ -41,6 +44,22 @@ impl<T, const N: usize> Stack for HVecStack<T, N> {
         self.data.iter().rev().skip(back).next().ok_or(Error::DataStackUnderflow)
     }
 
+    fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    fn clear(&mut self) {
+        self.data.clear()
+    }
+
+    fn as_slice(&self) -> &[Self::Item] {
+        &self.data
+    }
+
+    fn as_mut_slice(&mut self) -> &mut [Self::Item] {
+        &mut self.data
+    }
+
     fn last(&self) -> Result<&Self::Item, Error> {
         self.data.last().ok_or(Error::InternalError) // TODO: Wrong error!
     }
@@ -82,7 +101,7 @@ where
     BuiltinTok: Clone,
 {
     fn push(&mut self, data: RuntimeWord<BuiltinTok, SeqTok>) {
-        // TODO
+        // Callers check `is_full` first
         self.data.push(data).map_err(drop).unwrap()
     }
     fn pop(&mut self) -> Result<RuntimeWord<BuiltinTok, SeqTok>, Error> {
@@ -91,6 +110,12 @@ where
     fn last_mut(&mut self) -> Result<&mut RuntimeWord<BuiltinTok, SeqTok>, Error> {
         self.data.last_mut().ok_or(Error::FlowStackEmpty)
     }
+    fn clear(&mut self) {
+        self.data.clear()
+    }
+    fn is_full(&self) -> bool {
+        self.data.is_full()
+    }
 }
 
 #[derive(Clone)]
@@ -118,6 +143,23 @@ pub type NoStdRuntime<const DATA_SZ: usize, const FLOW_SZ: usize, const OUTBUF_S
     String<OUTBUF_SZ>,
 >;
 
+impl<const N: usize> OutputBuffer for String<N> {
+    fn clear(&mut self) {
+        String::clear(self)
+    }
+}
+
+/// A loaded dictionary referenced a builtin, by the contained name, that was
+/// not compiled into this binary
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MissingBuiltin<'a>(pub &'a str);
+
+impl<'a> From<MissingBuiltin<'a>> for Error {
+    fn from(_: MissingBuiltin<'a>) -> Self {
+        Error::MissingBuiltin
+    }
+}
+
 pub struct NoStdContext<
     const DATA_SZ: usize,
     const FLOW_SZ: usize,
@@ -138,9 +180,14 @@ impl<
         const SEQ_SZ: usize,
     > NoStdContext<DATA_SZ, FLOW_SZ, OUTBUF_SZ, SEQS_CT, SEQ_SZ>
 {
+    /// Load a deserialized dictionary. Builtins are resolved to this
+    /// binary's functions by name, and sequences keep the index they were
+    /// serialized with, so nothing stored depends on where `dict` was loaded.
+    ///
+    /// Fails if `dict` uses a builtin that is missing from `nostd_builtins`
     pub fn from_ser_dict<'a, const BIS_CT: usize>(
         dict: &SerDictFixed<'a, SEQS_CT, SEQ_SZ, BIS_CT>,
-    ) -> Self {
+    ) -> Result<Self, MissingBuiltin<'a>> {
         let rt = new_runtime();
         let mut bis: Vec<Builtin<DATA_SZ, FLOW_SZ, OUTBUF_SZ>, BIS_CT> = Vec::new();
 
@@ -150,7 +197,7 @@ impl<
                 .iter()
                 .find(|(k, _v)| k == bi)
                 .map(|(_k, v)| v)
-                .unwrap();
+                .ok_or(MissingBuiltin(bi))?;
 
             bis.push(*func).ok();
         }
@@ -186,9 +233,14 @@ impl<
             seqs_vec.push(seq_vec).ok();
         }
 
-        Self { rt, seq: seqs_vec }
+        Ok(Self { rt, seq: seqs_vec })
     }
 
+    /// Run until there is nothing left to execute. If a `key` needs input,
+    /// this fails with `Error::Input`, leaving the program paused: it may be
+    /// resumed by calling this again after `provide_input`. If a builtin
+    /// fails, the stacks are left as they were: call `rt.unwind()` before
+    /// running anything else
     pub fn run_blocking(&mut self) -> Result<(), Error> {
         loop {
             match self.rt.step() {
@@ -196,7 +248,11 @@ impl<
                     // The runtime yields back at every call to a "builtin". Here, I
                     // call the builtin immediately, but I could also yield further up,
                     // to be resumed at a later time
-                    ft.exec(&mut self.rt).unwrap();
+                    ft.exec(&mut self.rt)?;
+
+                    // Only the compiler knows the names of words, so there is
+                    // nothing here for `words` to list
+                    self.rt.take_host_request();
                 }
                 Ok(StepResult::Working(WhichToken::Ref(rtw))) => {
                     // The runtime yields back at every call to a "builtin". Here, I
@@ -209,9 +265,10 @@ impl<
                         .and_then(|n| n.get(rtw.idx))
                         .map(|n| n.clone());
 
-                    self.rt.provide_seq_tok(c).unwrap();
+                    self.rt.provide_seq_tok(c)?;
                 }
-                Ok(StepResult::Done) => break,
+                Ok(StepResult::Done) | Ok(StepResult::Quit) | Ok(StepResult::Idle) => break,
+                Ok(StepResult::NeedInput) => return Err(Error::Input),
                 Err(e) => {
                     // eprintln!("ERROR! -> {:?}", e);
                     return Err(e);
@@ -222,6 +279,127 @@ impl<
     }
 }
 
+/// A compiler for simple definitions that needs no allocator, for
+/// compiling on the device itself, e.g. from a serial REPL.
+///
+/// Only single-line definitions made of literals, builtins, and previously
+/// compiled words are supported: there is no control flow, and no
+/// optimization. Word names are kept here, up to `NAME_SZ` bytes each, while
+/// the compiled sequences are appended to the `NoStdContext`, after any
+/// sequences it already has
+pub struct NoStdCompiler<const SEQS_CT: usize, const NAME_SZ: usize> {
+    names: Vec<(String<NAME_SZ>, usize), SEQS_CT>,
+}
+
+impl<const SEQS_CT: usize, const NAME_SZ: usize> Default for NoStdCompiler<SEQS_CT, NAME_SZ> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const SEQS_CT: usize, const NAME_SZ: usize> NoStdCompiler<SEQS_CT, NAME_SZ> {
+    pub fn new() -> Self {
+        Self { names: Vec::new() }
+    }
+
+    /// Find the sequence index of a compiled word, ignoring case
+    pub fn lookup(&self, name: &str) -> Option<usize> {
+        self.names
+            .iter()
+            .find(|(n, _idx)| n.eq_ignore_ascii_case(name))
+            .map(|(_n, idx)| *idx)
+    }
+
+    /// Compile a single `: name ... ;` definition into `ctxt`, returning
+    /// the index of its sequence. Nothing is stored if compilation fails
+    pub fn compile_line<
+        const DATA_SZ: usize,
+        const FLOW_SZ: usize,
+        const OUTBUF_SZ: usize,
+        const SEQ_SZ: usize,
+    >(
+        &mut self,
+        ctxt: &mut NoStdContext<DATA_SZ, FLOW_SZ, OUTBUF_SZ, SEQS_CT, SEQ_SZ>,
+        line: &str,
+    ) -> Result<usize, Error> {
+        let mut toks = line.split_whitespace();
+
+        if toks.next() != Some(":") {
+            return Err(Error::NotADefinition);
+        }
+        let name = match toks.next() {
+            Some(";") | None => return Err(Error::MissingWordName),
+            Some(name) => name,
+        };
+        if self.lookup(name).is_some() {
+            return Err(Error::WordExists);
+        }
+
+        let mut seq = Vec::new();
+        let mut closed = false;
+
+        for tok in toks {
+            if closed {
+                return Err(Error::UnexpectedSemicolon);
+            }
+            if tok == ";" {
+                closed = true;
+                continue;
+            }
+            if tok == ":" {
+                return Err(Error::UnexpectedColon);
+            }
+
+            let word = if let Some(lit) = crate::parse_num(tok) {
+                RuntimeWord::LiteralVal(lit)
+            } else if let Some(idx) = self.lookup(tok) {
+                RuntimeWord::VerbSeq(VerbSeqInner::from_word(idx))
+            } else {
+                let bi = nostd_builtins::<DATA_SZ, FLOW_SZ, OUTBUF_SZ>()
+                    .iter()
+                    .find(|(k, _v)| k.eq_ignore_ascii_case(tok))
+                    .map(|(_k, v)| *v)
+                    .ok_or(Error::UnknownWord)?;
+                RuntimeWord::Verb(BuiltinToken::new(bi))
+            };
+
+            seq.push(word).map_err(|_| Error::CapacityExceeded)?;
+        }
+
+        if !closed {
+            return Err(Error::UnexpectedColon);
+        }
+
+        let mut lower = String::new();
+        for ch in name.chars() {
+            lower
+                .push(ch.to_ascii_lowercase())
+                .map_err(|_| Error::CapacityExceeded)?;
+        }
+
+        let idx = ctxt.seq.len();
+        if self.names.is_full() {
+            return Err(Error::CapacityExceeded);
+        }
+        ctxt.seq.push(seq).map_err(|_| Error::CapacityExceeded)?;
+        self.names.push((lower, idx)).ok();
+
+        Ok(idx)
+    }
+}
+
+/// Forwards runtime output to the `defmt` logger, at the `info` level
+#[cfg(feature = "defmt")]
+pub struct DefmtSink;
+
+#[cfg(feature = "defmt")]
+impl crate::OutputSink for DefmtSink {
+    fn flush(&mut self, output: &str) -> Result<(), Error> {
+        defmt::info!("{=str}", output);
+        Ok(())
+    }
+}
+
 pub type NoStdRuntimeWord<const DATA_SZ: usize, const FLOW_SZ: usize, const OUTBUF_SZ: usize> =
     RuntimeWord<BuiltinToken<DATA_SZ, FLOW_SZ, OUTBUF_SZ>, usize>;
 
@@ -235,6 +413,7 @@ pub fn new_runtime<const DATA_SZ: usize, const FLOW_SZ: usize, const OUTBUF_SZ:
     let ds = HVecStack::new(Error::DataStackEmpty);
     let rs = HVecStack::new(Error::RetStackEmpty);
     let fs = HVecStack::new(Error::FlowStackEmpty);
+    let mem = HVecStack::new(Error::BadAddress);
 
     // This is a generic Runtime type, I'll likely define two versions:
     // One with std-ability (for the host), and one no-std one, so users
@@ -243,8 +422,24 @@ pub fn new_runtime<const DATA_SZ: usize, const FLOW_SZ: usize, const OUTBUF_SZ:
         data_stk: ds,
         ret_stk: rs,
         flow_stk: fs,
+        mem,
         _pd_ty_t_f: PhantomData,
+        newline: Newline::Lf,
+        emit_mode: EmitMode::Lossy,
+        true_value: -1,
+        base: 10,
+        normalize_flags: false,
+        idle_when_empty: false,
+        stall_limit: None,
         cur_output: String::new(),
+        column: 0,
+        written: 0,
+        quitting: false,
+        awaiting_input: false,
+        next_key: None,
+        host_request: None,
+        stalled_jumps: 0,
+        last_observed: Default::default(),
     }
 }
 
@@ -254,22 +449,74 @@ pub fn nostd_builtins<const DATA_SZ: usize, const FLOW_SZ: usize, const OUTBUF_S
     fn(&mut NoStdRuntime<DATA_SZ, FLOW_SZ, OUTBUF_SZ>) -> Result<(), Error>,
 )] {
     &[
+        ("!", crate::builtins::bi_store),
+        ("*", crate::builtins::bi_mul),
         ("+", crate::builtins::bi_add),
+        ("+!", crate::builtins::bi_plus_store),
+        ("-", crate::builtins::bi_sub),
         (".", crate::builtins::bi_pop),
+        (".s", crate::builtins::bi_dot_s),
+        ("/", crate::builtins::bi_div),
+        ("/mod", crate::builtins::bi_divmod),
+        ("2!", crate::builtins::bi_2store),
+        ("2>r", crate::builtins::bi_retstk_2push),
+        ("2@", crate::builtins::bi_2fetch),
         ("2dup", crate::builtins::bi_2dup),
+        ("2r>", crate::builtins::bi_retstk_2pop),
+        ("2r@", crate::builtins::bi_retstk_2fetch),
         ("<", crate::builtins::bi_lt),
         ("=", crate::builtins::bi_eq),
         (">", crate::builtins::bi_gt),
         (">r", crate::builtins::bi_retstk_push),
+        ("@", crate::builtins::bi_fetch),
+        ("abort", crate::builtins::bi_abort),
+        ("allot", crate::builtins::bi_allot),
+        ("alpha?", crate::builtins::bi_is_alpha),
+        ("and", crate::builtins::bi_and),
+        ("bit-clr", crate::builtins::bi_bit_clr),
+        ("bit-set", crate::builtins::bi_bit_set),
+        ("bit-test", crate::builtins::bi_bit_test),
+        ("cell-bits", crate::builtins::bi_cell_bits),
+        ("cells", crate::builtins::bi_cells),
+        ("col", crate::builtins::bi_col),
         ("cr", crate::builtins::bi_cr),
+        ("decimal", crate::builtins::bi_decimal),
+        ("depth", crate::builtins::bi_depth),
+        ("digit?", crate::builtins::bi_is_digit),
         ("drop", crate::builtins::bi_drop),
         ("dup", crate::builtins::bi_dup),
         ("emit", crate::builtins::bi_emit),
+        ("exit", crate::builtins::bi_exit),
+        ("hex", crate::builtins::bi_hex),
+        ("i", crate::builtins::bi_i),
+        ("invert", crate::builtins::bi_invert),
+        ("j", crate::builtins::bi_j),
+        ("key", crate::builtins::bi_key),
+        ("lshift", crate::builtins::bi_lshift),
+        ("max-int", crate::builtins::bi_max_int),
+        ("min-int", crate::builtins::bi_min_int),
+        ("mod", crate::builtins::bi_mod),
+        ("or", crate::builtins::bi_or),
         ("pick", crate::builtins::bi_pick),
         ("PRIV_LOOP", crate::builtins::bi_priv_loop),
+        ("PRIV_PLUS_LOOP", crate::builtins::bi_priv_plus_loop),
+        ("quit", crate::builtins::bi_quit),
         ("r>", crate::builtins::bi_retstk_pop),
+        ("r>drop", crate::builtins::bi_retstk_drop),
+        ("rdrop", crate::builtins::bi_retstk_drop),
         ("roll", crate::builtins::bi_roll),
         ("rot", crate::builtins::bi_rot),
+        ("rshift", crate::builtins::bi_rshift),
+        ("smax", crate::builtins::bi_smax),
+        ("smin", crate::builtins::bi_smin),
+        ("space?", crate::builtins::bi_is_space),
+        ("spaces", crate::builtins::bi_spaces),
+        ("sum", crate::builtins::bi_sum),
         ("swap", crate::builtins::bi_swap),
+        ("tab-to", crate::builtins::bi_tab_to),
+        ("u.", crate::builtins::bi_u_dot),
+        ("unloop", crate::builtins::bi_unloop),
+        ("words", crate::builtins::bi_words),
+        ("xor", crate::builtins::bi_xor),
     ]
 }
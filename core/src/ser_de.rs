@@ -1,6 +1,10 @@
 use heapless::Vec as HVec;
 use serde::{Deserialize, Serialize};
 
+/// A single serialized word. This representation is position independent:
+/// `Verb` and `VerbSeq` hold an index into `bis` and `data` respectively,
+/// rather than an address, so a serialized program may be placed anywhere
+/// in memory, including in flash for execute-in-place targets
 #[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq)]
 pub enum SerWord {
     LiteralVal(i32),
@@ -22,6 +26,10 @@ pub struct SerDict {
     pub bis: Vec<String>,
 }
 
+/// The no_std form of a `SerDict`. Names are borrowed from the buffer this
+/// was deserialized from, which may be read-only. When deserialized with
+/// `postcard::from_bytes` (rather than a COBS variant, which decodes in
+/// place), a program can be loaded directly from a `&'static [u8]` in flash
 #[derive(Debug, Deserialize, Serialize)]
 pub struct SerDictFixed<'a, const SEQS_CT: usize, const SEQ_SZ: usize, const BIS_CT: usize> {
     pub data: HVec<HVec<SerWord, SEQ_SZ>, SEQS_CT>,
@@ -88,20 +96,21 @@ mod test {
             assert_eq!(ser_bis, des_bis);
         }
 
-        let mut ns_ctxt: NoStdContext<32, 16, 128, 4, 16> = NoStdContext::from_ser_dict(&loaded);
+        let mut ns_ctxt: NoStdContext<32, 16, 128, 4, 16> =
+            NoStdContext::from_ser_dict(&loaded).unwrap();
 
         let temp_compiled = RuntimeWord::VerbSeq(VerbSeqInner::from_word(1));
 
-        ns_ctxt.rt.push_exec(temp_compiled.clone());
-        ns_ctxt.rt.push_exec(RuntimeWord::LiteralVal(0));
+        ns_ctxt.rt.push_exec(temp_compiled.clone()).unwrap();
+        ns_ctxt.rt.push_exec(RuntimeWord::LiteralVal(0)).unwrap();
 
         ns_ctxt.run_blocking().unwrap();
 
         let out = ns_ctxt.rt.exchange_output();
         assert_eq!(out, "**");
 
-        ns_ctxt.rt.push_exec(temp_compiled);
-        ns_ctxt.rt.push_exec(RuntimeWord::LiteralVal(-1));
+        ns_ctxt.rt.push_exec(temp_compiled).unwrap();
+        ns_ctxt.rt.push_exec(RuntimeWord::LiteralVal(-1)).unwrap();
 
         ns_ctxt.run_blocking().unwrap();
 
@@ -109,6 +118,81 @@ mod test {
         assert_eq!(out, "*");
     }
 
+    #[test]
+    fn xip_from_static() {
+        let mut ctxt = Context::with_builtins(std_builtins());
+
+        ctxt.evaluate(vec![
+            ":".into(),
+            "star".into(),
+            "42".into(),
+            "emit".into(),
+            ";".into(),
+        ])
+        .unwrap();
+
+        ctxt.evaluate(vec![
+            ":".into(),
+            "stars".into(),
+            "0".into(),
+            "do".into(),
+            "star".into(),
+            "loop".into(),
+            ";".into(),
+        ])
+        .unwrap();
+
+        // Stand-in for a program placed in flash: a read-only buffer that
+        // outlives the context, and is never copied or modified
+        let ser = postcard::to_stdvec(&ctxt.serialize()).unwrap();
+        let prog: &'static [u8] = Box::leak(ser.into_boxed_slice());
+
+        let loaded: SerDictFixed<4, 16, 4> = postcard::from_bytes(prog).unwrap();
+        let mut ns_ctxt: NoStdContext<32, 16, 128, 4, 16> =
+            NoStdContext::from_ser_dict(&loaded).unwrap();
+
+        let stars = loaded
+            .data_map
+            .as_ref()
+            .unwrap()
+            .iter()
+            .position(|name| *name == "stars")
+            .unwrap();
+
+        ns_ctxt
+            .rt
+            .push_exec(RuntimeWord::VerbSeq(VerbSeqInner::from_word(stars)))
+            .unwrap();
+        ns_ctxt.rt.push_exec(RuntimeWord::LiteralVal(3)).unwrap();
+
+        ns_ctxt.run_blocking().unwrap();
+
+        let out = ns_ctxt.rt.exchange_output();
+        assert_eq!(out, "***");
+    }
+
+    #[test]
+    fn streamed_matches_in_memory() {
+        let mut ctxt = Context::with_builtins(std_builtins());
+
+        for line in [
+            ": star 42 emit ;",
+            ": nl 10 emit ;",
+            ": stars 0 do star loop ;",
+            ": big 1000000 -1 > if 200 stars else nl then ;",
+        ] {
+            ctxt.evaluate(line.split_whitespace().map(str::to_string).collect())
+                .unwrap();
+        }
+
+        let in_memory = postcard::to_stdvec(&ctxt.serialize()).unwrap();
+
+        let mut streamed = Vec::new();
+        ctxt.serialize_to_writer(&mut streamed).unwrap();
+
+        assert_eq!(in_memory, streamed);
+    }
+
     // #[test]
     #[allow(dead_code)]
     fn roundtrip2() {
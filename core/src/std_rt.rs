@@ -3,11 +3,14 @@ use std::marker::PhantomData;
 use std::sync::Arc;
 
 use crate::ser_de::SerWord;
+use crate::EmitMode;
+use crate::Newline;
+use crate::OutputBuffer;
 use crate::Runtime;
 use crate::RuntimeWord;
 use crate::{Error, ExecutionStack, Stack};
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct StdVecStack<T> {
     data: Vec<T>,
     err: Error,
@@ -44,6 +47,22 @@ impl<T> Stack for StdVecStack<T> {
         self.data.iter().rev().skip(back).next().ok_or(Error::DataStackUnderflow)
     }
 
+    fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    fn clear(&mut self) {
+        self.data.clear()
+    }
+
+    fn as_slice(&self) -> &[Self::Item] {
+        &self.data
+    }
+
+    fn as_mut_slice(&mut self) -> &mut [Self::Item] {
+        &mut self.data
+    }
+
     fn last(&self) -> Result<&Self::Item, Error> {
         self.data.last().ok_or(Error::InternalError) // TODO: Wrong error!
     }
@@ -70,6 +89,12 @@ where
     fn last_mut(&mut self) -> Result<&mut RuntimeWord<T, F>, Error> {
         self.data.last_mut().ok_or(Error::FlowStackEmpty)
     }
+    fn clear(&mut self) {
+        self.data.clear()
+    }
+    fn is_full(&self) -> bool {
+        false
+    }
 }
 
 #[derive(Clone)]
@@ -108,7 +133,13 @@ pub struct StdFuncSeq {
 
 pub type StdRuntimeWord = RuntimeWord<BuiltinToken, String>;
 
-type Builtin = fn(&mut StdRuntime) -> Result<(), Error>;
+impl OutputBuffer for String {
+    fn clear(&mut self) {
+        String::clear(self)
+    }
+}
+
+pub type Builtin = fn(&mut StdRuntime) -> Result<(), Error>;
 
 pub fn new_runtime() -> StdRuntime {
     // These are the only data structures required, and Runtime is generic over the
@@ -116,6 +147,7 @@ pub fn new_runtime() -> StdRuntime {
     let ds = StdVecStack::new(Error::DataStackEmpty);
     let rs = StdVecStack::new(Error::RetStackEmpty);
     let fs = StdVecStack::new(Error::FlowStackEmpty);
+    let mem = StdVecStack::new(Error::BadAddress);
 
     // This is a generic Runtime type, I'll likely define two versions:
     // One with std-ability (for the host), and one no-std one, so users
@@ -124,30 +156,98 @@ pub fn new_runtime() -> StdRuntime {
         data_stk: ds,
         ret_stk: rs,
         flow_stk: fs,
+        mem,
         _pd_ty_t_f: PhantomData,
+        newline: Newline::Lf,
+        emit_mode: EmitMode::Lossy,
+        true_value: -1,
+        base: 10,
+        normalize_flags: false,
+        idle_when_empty: false,
+        stall_limit: None,
         cur_output: String::new(),
+        column: 0,
+        written: 0,
+        quitting: false,
+        awaiting_input: false,
+        next_key: None,
+        host_request: None,
+        stalled_jumps: 0,
+        last_observed: Default::default(),
     }
 }
 
 pub fn std_builtins() -> &'static [(&'static str, fn(&mut StdRuntime) -> Result<(), Error>)] {
     &[
+        ("!", crate::builtins::bi_store),
+        ("*", crate::builtins::bi_mul),
         ("+", crate::builtins::bi_add),
+        ("+!", crate::builtins::bi_plus_store),
+        ("-", crate::builtins::bi_sub),
         (".", crate::builtins::bi_pop),
+        (".s", crate::builtins::bi_dot_s),
+        ("/", crate::builtins::bi_div),
+        ("/mod", crate::builtins::bi_divmod),
+        ("2!", crate::builtins::bi_2store),
+        ("2>r", crate::builtins::bi_retstk_2push),
+        ("2@", crate::builtins::bi_2fetch),
         ("2dup", crate::builtins::bi_2dup),
+        ("2r>", crate::builtins::bi_retstk_2pop),
+        ("2r@", crate::builtins::bi_retstk_2fetch),
         ("<", crate::builtins::bi_lt),
         ("=", crate::builtins::bi_eq),
         (">", crate::builtins::bi_gt),
         (">r", crate::builtins::bi_retstk_push),
+        ("@", crate::builtins::bi_fetch),
+        ("abort", crate::builtins::bi_abort),
+        ("allot", crate::builtins::bi_allot),
+        ("alpha?", crate::builtins::bi_is_alpha),
+        ("and", crate::builtins::bi_and),
+        ("bit-clr", crate::builtins::bi_bit_clr),
+        ("bit-set", crate::builtins::bi_bit_set),
+        ("bit-test", crate::builtins::bi_bit_test),
+        ("cell-bits", crate::builtins::bi_cell_bits),
+        ("cells", crate::builtins::bi_cells),
+        ("col", crate::builtins::bi_col),
         ("cr", crate::builtins::bi_cr),
+        ("decimal", crate::builtins::bi_decimal),
+        ("depth", crate::builtins::bi_depth),
+        ("digit?", crate::builtins::bi_is_digit),
         ("drop", crate::builtins::bi_drop),
         ("dup", crate::builtins::bi_dup),
         ("emit", crate::builtins::bi_emit),
+        ("exit", crate::builtins::bi_exit),
+        ("hex", crate::builtins::bi_hex),
+        ("i", crate::builtins::bi_i),
+        ("invert", crate::builtins::bi_invert),
+        ("j", crate::builtins::bi_j),
+        ("key", crate::builtins::bi_key),
+        ("lshift", crate::builtins::bi_lshift),
+        ("max-int", crate::builtins::bi_max_int),
+        ("min-int", crate::builtins::bi_min_int),
+        ("mod", crate::builtins::bi_mod),
+        ("or", crate::builtins::bi_or),
         ("pick", crate::builtins::bi_pick),
         ("PRIV_LOOP", crate::builtins::bi_priv_loop),
+        ("PRIV_PLUS_LOOP", crate::builtins::bi_priv_plus_loop),
+        ("quit", crate::builtins::bi_quit),
         ("r>", crate::builtins::bi_retstk_pop),
+        ("r>drop", crate::builtins::bi_retstk_drop),
+        ("rdrop", crate::builtins::bi_retstk_drop),
         ("roll", crate::builtins::bi_roll),
         ("rot", crate::builtins::bi_rot),
+        ("rshift", crate::builtins::bi_rshift),
+        ("smax", crate::builtins::bi_smax),
+        ("smin", crate::builtins::bi_smin),
+        ("space?", crate::builtins::bi_is_space),
+        ("spaces", crate::builtins::bi_spaces),
+        ("sum", crate::builtins::bi_sum),
         ("swap", crate::builtins::bi_swap),
+        ("tab-to", crate::builtins::bi_tab_to),
+        ("u.", crate::builtins::bi_u_dot),
+        ("unloop", crate::builtins::bi_unloop),
+        ("words", crate::builtins::bi_words),
+        ("xor", crate::builtins::bi_xor),
     ]
 }
 
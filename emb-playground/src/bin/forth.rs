@@ -2,7 +2,7 @@
 #![no_std]
 
 use emb_playground as _; // global logger + panicking-behavior + memory layout
-use anachro_forth_core::{RuntimeWord, VerbSeqInner, nostd_rt::NoStdContext, ser_de::SerDictFixed};
+use anachro_forth_core::{RuntimeWord, VerbSeqInner, nostd_rt::{DefmtSink, NoStdContext}, ser_de::SerDictFixed};
 use groundhog_nrf52::GlobalRollingTimer;
 use groundhog::RollingTimer;
 
@@ -17,10 +17,11 @@ fn main() -> ! {
     groundhog_nrf52::GlobalRollingTimer::init(board.TIMER0);
 
     let loaded: SerDictFixed<4, 16, 4> = postcard::from_bytes_cobs(prog).unwrap();
-    let mut ns_ctxt: NoStdContext<32, 16, 128, 4, 16> = NoStdContext::from_ser_dict(&loaded);
+    let mut ns_ctxt: NoStdContext<32, 16, 128, 4, 16> =
+        NoStdContext::from_ser_dict(&loaded).unwrap();
 
     let temp_compiled = RuntimeWord::VerbSeq(VerbSeqInner::from_word(1));
-    ns_ctxt.rt.push_exec(temp_compiled.clone());
+    ns_ctxt.rt.push_exec(temp_compiled.clone()).unwrap();
 
     let timer = GlobalRollingTimer::new();
     let now = timer.get_ticks();
@@ -28,6 +29,7 @@ fn main() -> ! {
     let elapsed = timer.micros_since(now);
 
     defmt::info!("lol: {}", elapsed);
+    ns_ctxt.rt.flush_output(&mut DefmtSink).unwrap();
 
     emb_playground::exit()
 }
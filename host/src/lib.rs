@@ -1 +1,82 @@
+//! # The `.a4` file format
+//!
+//! A compiled `.a4` file consists of a short header, followed by the
+//! postcard-serialized `SerDict`, encoded with rzcobs and terminated
+//! with a single zero byte.
+//!
+//! Files produced before the header was introduced are considered
+//! "version 0". These are no longer loaded directly, but may be upgraded
+//! with [`migrate`] (or `a4 migrate`).
+//!
+//! ## Execute-in-place output
+//!
+//! Targets that run programs directly from flash can't decode the rzcobs
+//! framing, as it must be done in RAM. For these, [`encode_xip`] (or
+//! `a4 compile --xip`) produces the bare postcard-serialized `SerDict`,
+//! without a header or framing, which may be deserialized into a
+//! `SerDictFixed` in place with `postcard::from_bytes`. These files can't be
+//! loaded by the host tools.
 
+use a4_core::{ser_de::SerDict, Error};
+
+/// Marks the start of a versioned `.a4` file
+pub const MAGIC: [u8; 2] = *b"A4";
+
+/// The current version of the `.a4` file format
+pub const VERSION: u8 = 1;
+
+/// The version of the given `.a4` file. Files without a header are
+/// reported as version 0
+pub fn file_version(buf: &[u8]) -> u8 {
+    match buf {
+        [m0, m1, version, ..] if [*m0, *m1] == MAGIC => *version,
+        _ => 0,
+    }
+}
+
+/// Encode a dictionary as a current version `.a4` file
+pub fn encode(dict: &SerDict) -> Vec<u8> {
+    let mut out = MAGIC.to_vec();
+    out.push(VERSION);
+    out.extend(encode_payload(dict));
+    out
+}
+
+/// Encode a dictionary for execution in place. See the module docs
+pub fn encode_xip(dict: &SerDict) -> Vec<u8> {
+    postcard::to_stdvec(dict).unwrap()
+}
+
+/// Decode a current version `.a4` file
+pub fn decode(buf: &[u8]) -> Result<SerDict, Error> {
+    if file_version(buf) != VERSION {
+        return Err(Error::UnsupportedVersion);
+    }
+    decode_payload(&buf[MAGIC.len() + 1..])
+}
+
+/// Upgrade an `.a4` file of any known version to the current version
+pub fn migrate(buf: &[u8]) -> Result<Vec<u8>, Error> {
+    match file_version(buf) {
+        // Version 0 files are the bare payload, without any header
+        0 => Ok(encode(&decode_payload(buf)?)),
+        VERSION => Ok(buf.to_vec()),
+        _ => Err(Error::UnsupportedVersion),
+    }
+}
+
+fn encode_payload(dict: &SerDict) -> Vec<u8> {
+    let pcser = postcard::to_stdvec(dict).unwrap();
+    let mut zc = rzcobs::encode(&pcser);
+    zc.push(0);
+    zc
+}
+
+fn decode_payload(buf: &[u8]) -> Result<SerDict, Error> {
+    let buf = match buf.split_last() {
+        Some((0x00, rest)) => rest,
+        _ => return Err(Error::Input),
+    };
+    let unrz = rzcobs::decode(buf).map_err(|_| Error::Input)?;
+    postcard::from_bytes(&unrz).map_err(|_| Error::Input)
+}
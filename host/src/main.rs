@@ -6,7 +6,7 @@ use std::path::PathBuf;
 
 use structopt::StructOpt;
 
-use a4_core::compiler::Context;
+use a4_core::compiler::{tokenize, Context};
 use a4_core::std_rt::std_builtins;
 use a4_core::{Error, StepResult, WhichToken};
 
@@ -47,6 +47,22 @@ enum Opt {
         /// This is useful for reducing bytes-on-the-wire
         #[structopt(short, long = "omit-word-names")]
         omit_word_names: bool,
+
+        /// Emit the bare serialized program, without a header or framing,
+        /// so it can be executed in place from flash
+        #[structopt(long)]
+        xip: bool,
+    },
+
+    /// Upgrade an ".a4" compiled output from an older version of the
+    /// compiler to the current file format
+    Migrate {
+        /// The compiled file to upgrade
+        input: PathBuf,
+
+        /// The upgraded output path. If none is provided, the input file
+        /// will be replaced
+        output: Option<PathBuf>,
     },
 }
 
@@ -62,49 +78,61 @@ fn main() -> Result<(), Error> {
             input,
             output,
             omit_word_names,
+            xip,
         } => {
             let output = output.unwrap_or({
                 let mut out = input.clone();
                 assert!(out.set_extension("a4"), "no filename?");
                 out
             });
-            compile_main(input, output, omit_word_names)?;
+            compile_main(input, output, omit_word_names, xip)?;
         }
         Opt::Run { input, debug } => {
             run_main(input, debug)?;
         }
+        Opt::Migrate { input, output } => {
+            let output = output.unwrap_or_else(|| input.clone());
+            migrate_main(input, output)?;
+        }
     }
 
     Ok(())
 }
 
-fn compile_main(input: PathBuf, output: PathBuf, omit_word_names: bool) -> Result<(), Error> {
+fn compile_main(
+    input: PathBuf,
+    output: PathBuf,
+    omit_word_names: bool,
+    xip: bool,
+) -> Result<(), Error> {
     let mut ctxt = Context::with_builtins(std_builtins());
+    ctxt.compile_only = true;
 
     let source = read_to_string(&input).map_err(|_| Error::Input)?;
 
-    for line in source.lines() {
-        let parts = line.split_whitespace().map(str::to_string).collect();
-        ctxt.evaluate(parts)?;
-    }
+    for (idx, line) in source.lines().enumerate() {
+        let parts = tokenize(line);
+        if let Err(e) = ctxt.evaluate(parts) {
+            if let Error::NotADefinition = e {
+                eprintln!(
+                    "
+ERROR: Found a non-definition on line {}:
 
-    let mut extras = false;
-    ctxt.dict.data.retain(|k, _| {
-        let keep = !k.starts_with("__");
-        if !keep {
-            extras = true;
-        }
-        keep
-    });
+    {}
 
-    eprintln!(
-        "
-WARNING: Found at least one non-definition in the input file.
-These line(s) will NOT be serialized or executed. Please review
+Non-definitions are never serialized or executed. Please review
 your source file to ensure it ONLY includes definitions, which
 start with a ':', and end with a ';'.
-"
-    );
+",
+                    idx + 1,
+                    line.trim()
+                );
+            } else {
+                print_line_error(&ctxt, &e, idx + 1, line);
+            }
+            return Err(e);
+        }
+    }
 
     let mut ser = ctxt.serialize();
 
@@ -112,9 +140,11 @@ start with a ':', and end with a ';'.
         ser.data_map = None;
     }
 
-    let pcser = postcard::to_stdvec(&ser).unwrap();
-    let mut zc = rzcobs::encode(&pcser);
-    zc.push(0);
+    let zc = if xip {
+        a4::encode_xip(&ser)
+    } else {
+        a4::encode(&ser)
+    };
 
     write(&output, &zc).map_err(|_| Error::OutputFormat)?;
 
@@ -128,60 +158,59 @@ start with a ':', and end with a ';'.
     Ok(())
 }
 
-fn run_main(input: PathBuf, debug: bool) -> Result<(), Error> {
-    let mut ctxt = Context::with_builtins(std_builtins());
+fn migrate_main(input: PathBuf, output: PathBuf) -> Result<(), Error> {
+    let old = std::fs::read(&input).map_err(|_| Error::Input)?;
+    let version = a4::file_version(&old);
+    let new = a4::migrate(&old)?;
 
-    let input = read_to_string(input).map_err(|_| Error::Input)?;
+    write(&output, &new).map_err(|_| Error::OutputFormat)?;
 
-    for line in input.lines() {
-        let input: Vec<String> = line.split_whitespace().map(str::to_string).collect();
+    println!("Input file:  {:?} (version {})", input, version);
+    println!("Output file: {:?} (version {})", output, a4::VERSION);
 
-        if input.is_empty() {
-            continue;
-        }
+    Ok(())
+}
+
+fn run_main(input: PathBuf, debug: bool) -> Result<(), Error> {
+    let mut ctxt = Context::with_builtins(std_builtins());
 
-        if debug {
+    let source = read_to_string(input).map_err(|_| Error::Input)?;
+    let mut lines = source.lines().map(str::to_string).inspect(|line| {
+        if debug && !line.trim().is_empty() {
             println!("=> {}", line);
         }
+    });
 
-        ctxt.evaluate(input)?;
-        let is_ok = loop {
-            match ctxt.step() {
-                Ok(StepResult::Working(WhichToken::Single(ft))) => {
-                    // The runtime yields back at every call to a "builtin". Here, I
-                    // call the builtin immediately, but I could also yield further up,
-                    // to be resumed at a later time
-                    ft.exec(&mut ctxt.rt).unwrap();
-                }
-                Ok(StepResult::Working(WhichToken::Ref(rtw))) => {
-                    // The runtime yields back at every call to a "builtin". Here, I
-                    // call the builtin immediately, but I could also yield further up,
-                    // to be resumed at a later time
-
-                    let c = ctxt
-                        .dict
-                        .data
-                        .get(&rtw.tok)
-                        .and_then(|n| n.inner.get(rtw.idx))
-                        .map(|n| n.clone().word);
-
-                    ctxt.rt.provide_seq_tok(c).unwrap();
-                }
-                Ok(StepResult::Done) => break true,
-                Err(e) => {
-                    eprintln!("ERROR! -> {:?}", e);
-                    break false;
-                }
-            }
-            if debug {
-                println!("# {:?} - {:?}", ctxt.data_stack().data(), ctxt.return_stack().data());
+    // The number of lines taken by earlier calls to `evaluate_lines`
+    let mut line_no = 0;
+    loop {
+        let res = ctxt.evaluate_lines(lines.by_ref());
+        print_warnings(&mut ctxt);
+        let err = match res {
+            Ok(output) => {
+                print!("{}", output);
+                return Ok(());
             }
+            Err(err) => err,
         };
-        ctxt.dict.data.retain(|k, _| !k.starts_with("__"));
-        print(&mut ctxt, is_ok);
-    }
+        print!("{}", err.output);
+        line_no += err.line;
+
+        // A `key` pauses the line, which carries on once given a character
+        let mut res = Err(err.error);
+        while let Err(Error::Input) = res {
+            let ch = read_key().map_err(|_| Error::Input)?;
+            ctxt.rt.provide_input(ch)?;
+            res = ctxt.run_blocking();
+            print!("{}", ctxt.output());
+        }
 
-    Ok(())
+        if let Err(e) = res {
+            let line = source.lines().nth(line_no - 1).unwrap_or_default();
+            print_line_error(&ctxt, &e, line_no, line);
+            return Err(e);
+        }
+    }
 }
 
 fn repl_main(input: Option<PathBuf>, debug: bool) -> Result<(), Error> {
@@ -193,11 +222,19 @@ fn repl_main(input: Option<PathBuf>, debug: bool) -> Result<(), Error> {
                 let mut f = std::fs::File::open(pb).unwrap();
                 let mut buf = Vec::new();
                 f.read_to_end(&mut buf).unwrap();
-                assert_eq!(Some(&0x00), buf.last());
-                buf.pop();
-                let unrz = rzcobs::decode(&buf).unwrap();
-                let deser = postcard::from_bytes(&unrz).unwrap();
+                let deser = match a4::decode(&buf) {
+                    Ok(deser) => deser,
+                    Err(Error::UnsupportedVersion) => {
+                        eprintln!(
+                            "ERROR: Unsupported file version {}. Try `a4 migrate`?",
+                            a4::file_version(&buf)
+                        );
+                        return Err(Error::UnsupportedVersion);
+                    }
+                    Err(e) => return Err(e),
+                };
                 ctxt.load_ser_dict(&deser);
+                print_warnings(&mut ctxt);
             }
             Some(_) => todo!("No .fth loading yet, sorry"),
             None => {
@@ -209,30 +246,44 @@ fn repl_main(input: Option<PathBuf>, debug: bool) -> Result<(), Error> {
 
     loop {
         let input = read().map_err(|_| Error::Input)?;
-        ctxt.evaluate(input)?;
+        if let Err(e) = ctxt.evaluate(input) {
+            print_eval_error(&ctxt, &e);
+            print(&mut ctxt, false);
+            continue;
+        }
+        print_warnings(&mut ctxt);
         let is_ok = loop {
             match ctxt.step() {
                 Ok(StepResult::Working(WhichToken::Single(ft))) => {
                     // The runtime yields back at every call to a "builtin". Here, I
                     // call the builtin immediately, but I could also yield further up,
                     // to be resumed at a later time
-                    ft.exec(&mut ctxt.rt).unwrap();
+                    let res = ft.exec(&mut ctxt.rt);
+                    if let Err(e) = res.and_then(|_| ctxt.serve_host_request()) {
+                        // A failed builtin leaves the stacks as they were, so
+                        // clear them before the next line
+                        print_eval_error(&ctxt, &e);
+                        ctxt.rt.unwind();
+                        break false;
+                    }
                 }
                 Ok(StepResult::Working(WhichToken::Ref(rtw))) => {
                     // The runtime yields back at every call to a "builtin". Here, I
                     // call the builtin immediately, but I could also yield further up,
                     // to be resumed at a later time
 
-                    let c = ctxt
-                        .dict
-                        .data
-                        .get(&rtw.tok)
-                        .and_then(|n| n.inner.get(rtw.idx))
-                        .map(|n| n.clone().word);
-
-                    ctxt.rt.provide_seq_tok(c).unwrap();
+                    let c = ctxt.resolve_seq(&rtw);
+                    if let Err(e) = ctxt.rt.provide_seq_tok(c) {
+                        print_eval_error(&ctxt, &e);
+                        ctxt.rt.unwind();
+                        break false;
+                    }
+                }
+                Ok(StepResult::Done) | Ok(StepResult::Quit) | Ok(StepResult::Idle) => break true,
+                Ok(StepResult::NeedInput) => {
+                    let ch = read_key().map_err(|_| Error::Input)?;
+                    ctxt.rt.provide_input(ch)?;
                 }
-                Ok(StepResult::Done) => break true,
                 Err(e) => {
                     eprintln!("ERROR! -> {:?}", e);
                     break false;
@@ -242,7 +293,6 @@ fn repl_main(input: Option<PathBuf>, debug: bool) -> Result<(), Error> {
                 println!("# {:?} - {:?}", ctxt.data_stack().data(), ctxt.return_stack().data());
             }
         };
-        ctxt.dict.data.retain(|k, _| !k.starts_with("__"));
         print(&mut ctxt, is_ok);
     }
 }
@@ -253,7 +303,13 @@ fn read() -> IoResult<Vec<String>> {
     let mut buf = String::new();
     stdin().read_line(&mut buf)?;
 
-    Ok(buf.split_whitespace().map(str::to_string).collect())
+    Ok(tokenize(&buf))
+}
+
+fn read_key() -> Result<i32, std::io::Error> {
+    let mut buf = [0u8; 1];
+    stdin().read_exact(&mut buf)?;
+    Ok(buf[0].into())
 }
 
 fn print(ctxt: &mut Context, good: bool) {
@@ -264,3 +320,50 @@ fn print(ctxt: &mut Context, good: bool) {
         println!(" bad ");
     }
 }
+
+fn print_eval_error(ctxt: &Context, err: &Error) {
+    eprintln!("ERROR! -> {}", describe_eval_error(ctxt, err));
+}
+
+/// Report an error from evaluating a line of a source file, with the line
+/// number, and the column of the unknown word if that was the problem
+fn print_line_error(ctxt: &Context, err: &Error, line_no: usize, line: &str) {
+    let col = match (err, ctxt.unknown_word()) {
+        (Error::UnknownWord, Some(word)) => token_column(line, word),
+        _ => None,
+    };
+    let at = match col {
+        Some(col) => format!("line {}, column {}", line_no, col),
+        None => format!("line {}", line_no),
+    };
+    eprintln!("ERROR! -> {}: {}", at, describe_eval_error(ctxt, err));
+}
+
+fn describe_eval_error(ctxt: &Context, err: &Error) -> String {
+    match (err, ctxt.unknown_word(), ctxt.underflow_word()) {
+        (Error::UnknownWord, Some(word), _) => format!("unknown word '{}'", word),
+        (Error::DataStackUnderflow, _, Some(word)) => format!("stack underflow in '{}'", word),
+        _ => format!("{:?}", err),
+    }
+}
+
+/// The column (counting characters from one) of the first whitespace
+/// separated token in `line` matching `word`, ignoring case
+fn token_column(line: &str, word: &str) -> Option<usize> {
+    let mut pos = 0;
+    for tok in line.split_whitespace() {
+        let start = pos + line[pos..].find(tok)?;
+        if tok.to_lowercase() == word {
+            return Some(line[..start].chars().count() + 1);
+        }
+        pos = start + tok.len();
+    }
+    None
+}
+
+fn print_warnings(ctxt: &mut Context) {
+    for warning in ctxt.warnings() {
+        eprintln!("WARNING: {}", warning.message);
+    }
+    ctxt.clear_warnings();
+}
@@ -1,5 +1,11 @@
-use a4_core::compiler::Context;
-use a4_core::{std_rt::std_builtins, StepResult, WhichToken};
+use std::time::Duration;
+
+use a4_core::compiler::{tokenize, Context, Dispatch, Profiler, StepEvent, Warning};
+use a4_core::{
+    ser_de::SerWord,
+    std_rt::{std_builtins, StdRuntime},
+    EmitMode, Error, Newline, RuntimeWord, Stack, StepResult, VerbSeqInner, WhichToken, WordId,
+};
 
 const SINGLE_LINE_CASES: &[(&str, &str)] = &[
     // Basic output
@@ -44,6 +50,98 @@ const SINGLE_LINE_CASES: &[(&str, &str)] = &[
         "1 1 if 42 emit if 42 emit else 42 emit 42 emit then then",
         "**",
     ),
+    // Double-cell return stack
+    ("1 2 2>r 2r> . .", "2\n1\n"),
+    ("1 2 2>r 2r@ 2r> + + + .", "6\n"),
+    ("2 0 do 7 42 2>r 2r> emit drop loop", "**"),
+    ("1 2 >r rdrop .", "1\n"),
+    ("1 2 >r r>drop .", "1\n"),
+    // Pick and roll
+    ("1 2 3 2 pick . . . .", "1\n3\n2\n1\n"),
+    ("1 2 3 2 roll . . .", "1\n3\n2\n"),
+    ("1 2 3 0 roll . . .", "3\n2\n1\n"),
+    // Loops near the limits of the index
+    ("2147483647 2147483645 do 42 emit loop", "**"),
+    ("-2147483647 -2147483648 do 42 emit loop", "*"),
+    ("-2147483646 -2147483648 do 42 emit loop", "**"),
+    // Printing the extremes of the cell
+    ("-2147483648 .", "-2147483648\n"),
+    ("2147483647 .", "2147483647\n"),
+    ("2147483647 1 + .", "-2147483648\n"),
+    // Printing in hex, and unsigned
+    ("-1 .", "-1\n"),
+    ("hex -1 .", "ffffffff\n"),
+    ("hex 255 . decimal 255 .", "ff\n255\n"),
+    ("hex -2147483648 .", "80000000\n"),
+    ("-1 u. hex -1 u.", "4294967295\nffffffff\n"),
+    ("-2147483648 u. 0 u.", "2147483648\n0\n"),
+    // Multiplication
+    ("6 7 * emit", "*"),
+    ("6 7 * .", "42\n"),
+    ("-1 -1 * . 65536 65536 * .", "1\n0\n"),
+    // Division, truncating towards zero
+    ("7 2 / . -7 2 / . 7 -2 / .", "3\n-3\n-3\n"),
+    ("-2147483648 -1 / .", "-2147483648\n"),
+    // Remainders take the sign of the dividend
+    ("7 2 mod . -7 2 mod . 7 -2 mod .", "1\n-1\n1\n"),
+    ("7 2 /mod . . -7 2 /mod . .", "3\n1\n-3\n-1\n"),
+    ("-2147483648 -1 /mod . .", "-2147483648\n0\n"),
+    // Bitwise operators
+    ("12 10 and . 12 10 or . 12 10 xor .", "8\n14\n6\n"),
+    ("0 invert . -1 invert . 5 invert .", "-1\n0\n-6\n"),
+    ("1 2 < 3 4 < and .", "-1\n"),
+    ("1 4 lshift . 1 31 lshift .", "16\n-2147483648\n"),
+    ("16 4 rshift . -1 28 rshift . -1 0 rshift .", "1\n15\n-1\n"),
+    // Stack depth
+    ("depth .", "0\n"),
+    ("1 2 3 depth . . . .", "3\n3\n2\n1\n"),
+    (".s", "<0> "),
+    ("1 -2 3 .s . . .", "<3> 1 -2 3 3\n-2\n1\n"),
+    // Whole stack reductions
+    ("1 2 3 4 5 sum .", "15\n"),
+    ("7 sum .", "7\n"),
+    ("3 -9 42 7 smax emit", "*"),
+    ("3 -9 42 7 smin .", "-9\n"),
+    ("2147483647 1 sum .", "-2147483648\n"),
+    // Character classes
+    ("48 digit? . 57 digit? . 65 digit? .", "-1\n-1\n0\n"),
+    ("47 digit? . 58 digit? . -1 digit? .", "0\n0\n0\n"),
+    ("65 alpha? . 90 alpha? . 97 alpha? .", "-1\n-1\n-1\n"),
+    ("122 alpha? .", "-1\n"),
+    ("64 alpha? . 91 alpha? . 193 alpha? .", "0\n0\n0\n"),
+    ("32 space? . 9 space? . 10 space? .", "-1\n-1\n-1\n"),
+    ("13 space? .", "-1\n"),
+    ("0 space? . 65 space? . 288 space? .", "0\n0\n0\n"),
+    // Environment queries
+    ("cell-bits .", "32\n"),
+    ("max-int . min-int .", "2147483647\n-2147483648\n"),
+    ("max-int 1 + min-int = .", "-1\n"),
+    // Bit manipulation
+    ("0 3 bit-set .", "8\n"),
+    ("0 31 bit-set .", "-2147483648\n"),
+    ("15 1 bit-clr .", "13\n"),
+    ("13 1 bit-clr .", "13\n"),
+    ("5 2 bit-test . 5 1 bit-test .", "-1\n0\n"),
+    ("-1 31 bit-test .", "-1\n"),
+    // Subtraction
+    ("10 3 - .", "7\n"),
+    ("3 10 - .", "-7\n"),
+    ("min-int 1 - max-int = .", "-1\n"),
+    // Number literals
+    ("-10 . 0x10 . 0b101 .", "-10\n16\n5\n"),
+    ("-0x10 . -0b101 . -0x0 .", "-16\n-5\n0\n"),
+    ("0xFF . 0xffffffff .", "255\n-1\n"),
+    ("$10 . %101 . $FF . -$10 . -%11 .", "16\n5\n255\n-16\n-3\n"),
+    ("1_000 . -1_000_000 .", "1000\n-1000000\n"),
+    ("0xffff_ffff . %1010_0101 .", "-1\n165\n"),
+    ("-0x80000000 min-int = .", "-1\n"),
+    ("0x7fffffff max-int = .", "-1\n"),
+    // Strings, comments and chars may hold anything
+    (r#"." a ; b""#, "a ; b"),
+    (r#"." a \ b""#, r"a \ b"),
+    (r#"."  two  spaces ""#, " two  spaces "),
+    ("char ; emit char : emit char \\ emit", ";:\\"),
+    ("( ; : \\ ) 42 emit", "*"),
 ];
 
 const MULTI_LINE_CASES: &[(&str, &str)] = &[
@@ -77,6 +175,141 @@ const MULTI_LINE_CASES: &[(&str, &str)] = &[
         "#,
         "************",
     ),
+    (
+        r#"
+            : t 0 begin 42 emit 1 + dup 3 = until drop ;
+            t
+        "#,
+        "***",
+    ),
+    (
+        r#"
+            : countdown begin dup 0 > while dup . 1 - repeat drop ;
+            3 countdown
+            0 countdown
+        "#,
+        "3\n2\n1\n",
+    ),
+    (
+        r#"
+            : t 5 0 do i . loop ;
+            t
+        "#,
+        "0\n1\n2\n3\n4\n",
+    ),
+    (
+        r#"
+            : t 2 0 do 3 0 do j 10 * i + . loop loop ;
+            t
+        "#,
+        "0\n1\n2\n10\n11\n12\n",
+    ),
+    (
+        r#"
+            : t 10 0 do i . 2 +loop ;
+            t
+        "#,
+        "0\n2\n4\n6\n8\n",
+    ),
+    (
+        r#"
+            : t 0 3 do i . -1 +loop ;
+            t
+        "#,
+        "3\n2\n1\n0\n",
+    ),
+    (
+        r#"
+            : t 10 0 do i 5 = if leave then i . loop ;
+            t
+        "#,
+        "0\n1\n2\n3\n4\n",
+    ),
+    (
+        r#"
+            : t 2 0 do 9 0 do i 1 = if leave then 42 emit loop 46 emit loop ;
+            t
+        "#,
+        "*.*.",
+    ),
+    (
+        r#"
+            : t case 1 of 65 emit endof 2 of 66 emit endof 67 emit endcase depth . ;
+            1 t 2 t 3 t
+        "#,
+        "A0\nB0\nC0\n",
+    ),
+    (
+        r#"
+            : t ( a b -- c ) + ;
+            : u ( n -- ) 0 > if ( positive ) 43 emit else ( not ( even ) zero ) 45 emit then ;
+            1 2 t . 5 u -5 u
+        "#,
+        "3\n+-",
+    ),
+    (
+        r#"
+            char * emit
+            : star [char] * emit ;
+            star CHAR A emit [char] a . char 🦀 .
+        "#,
+        "**A97\n129408\n",
+    ),
+    (
+        r#"
+            : hi ." hi" ;
+            hi
+            ." Hello,   World!" 1 if ." yes" then
+        "#,
+        "hiHello,   World!yes",
+    ),
+    (
+        r#"
+            : say ." a ; b" char ; emit ( ; ) ;
+            say
+        "#,
+        "a ; b;",
+    ),
+    (
+        r#"
+            variable x 5 x ! x @ .
+            variable y
+            : bump x @ 1 + x ! ;
+            bump bump x @ . y @ .
+        "#,
+        "5\n7\n0\n",
+    ),
+    (
+        r#"
+            variable n 5 n ! 3 n +! n @ .
+            -10 n +! n @ .
+        "#,
+        "8\n-2\n",
+    ),
+    (
+        r#"
+            variable arr 9 cells allot variable after
+            42 arr 3 cells + ! 7 after !
+            arr 3 cells + @ . arr 9 cells + @ . after arr - .
+        "#,
+        "42\n0\n10\n",
+    ),
+    (
+        r#"
+            variable pair 1 allot
+            3 4 pair 2! pair 2@ . .
+            pair @ . pair 1 + @ .
+        "#,
+        "4\n3\n4\n3\n",
+    ),
+    (
+        r#"
+            \ A whole line comment
+            : star 42 emit ; \ prints a star
+            star star \ star
+        "#,
+        "**",
+    ),
 ];
 
 /// Creates a clean engine
@@ -89,7 +322,8 @@ fn single_lines() {
         println!("{:?}", ctxt.serialize());
         loop {
             match ctxt.step().unwrap() {
-                StepResult::Done => break,
+                StepResult::Done | StepResult::Quit | StepResult::Idle => break,
+                StepResult::NeedInput => unreachable!(),
                 StepResult::Working(WhichToken::Single(ft)) => {
                     // The runtime yields back at every call to a "builtin". Here, I
                     // call the builtin immediately, but I could also yield further up,
@@ -101,13 +335,7 @@ fn single_lines() {
                     // call the builtin immediately, but I could also yield further up,
                     // to be resumed at a later time
 
-                    let c = ctxt
-                        .dict
-                        .data
-                        .get(&rtw.tok)
-                        .and_then(|n| n.inner.get(rtw.idx))
-                        .map(|n| n.clone().word);
-
+                    let c = ctxt.resolve_seq(&rtw);
                     ctxt.rt.provide_seq_tok(c).unwrap();
                 }
             }
@@ -131,7 +359,8 @@ fn multi_lines() {
             println!("{:?}", ctxt.serialize());
             loop {
                 match ctxt.step().unwrap() {
-                    StepResult::Done => break,
+                    StepResult::Done | StepResult::Quit | StepResult::Idle => break,
+                    StepResult::NeedInput => unreachable!(),
                     StepResult::Working(WhichToken::Single(ft)) => {
                         // The runtime yields back at every call to a "builtin". Here, I
                         // call the builtin immediately, but I could also yield further up,
@@ -143,13 +372,7 @@ fn multi_lines() {
                         // call the builtin immediately, but I could also yield further up,
                         // to be resumed at a later time
 
-                        let c = ctxt
-                            .dict
-                            .data
-                            .get(&rtw.tok)
-                            .and_then(|n| n.inner.get(rtw.idx))
-                            .map(|n| n.clone().word);
-
+                        let c = ctxt.resolve_seq(&rtw);
                         ctxt.rt.provide_seq_tok(c).unwrap();
                     }
                 }
@@ -164,6 +387,1347 @@ fn multi_lines() {
     }
 }
 
+#[test]
+fn bare_lines_leave_no_words() {
+    let mut ctxt = Context::with_builtins(std_builtins());
+
+    ctxt.evaluate(s(": star 42 emit ;")).unwrap();
+    for line in ["star star", "1 if star then", "3 0 do star loop"] {
+        ctxt.evaluate(s(line)).unwrap();
+        ctxt.run_blocking().unwrap();
+    }
+
+    assert_eq!("******", &ctxt.output());
+    assert_eq!(
+        vec!["star"],
+        ctxt.dict
+            .data
+            .keys()
+            .map(String::as_str)
+            .collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn pending_bare_lines() -> Result<(), Error> {
+    let mut ctxt = Context::with_builtins(std_builtins());
+
+    // Each line keeps its own code. The most recently evaluated runs first
+    ctxt.evaluate(s("42 emit"))?;
+    ctxt.evaluate(s("43 emit 44 emit"))?;
+    ctxt.run_blocking()?;
+    assert_eq!(ctxt.output(), "+,*");
+
+    // A line paused on `key` resumes after one evaluated in the meantime
+    ctxt.evaluate(s("key emit 42 emit"))?;
+    assert!(matches!(ctxt.run_blocking(), Err(Error::Input)));
+    ctxt.evaluate(s("43 emit"))?;
+    ctxt.rt.provide_input(45)?;
+    ctxt.run_blocking()?;
+    assert_eq!(ctxt.output(), "+-*");
+    Ok(())
+}
+
+#[test]
+fn underflow_leaves_stack_untouched() {
+    let mut ctxt = Context::with_builtins(std_builtins());
+
+    ctxt.evaluate(s("5 2dup")).unwrap();
+    assert!(matches!(
+        ctxt.run_blocking(),
+        Err(Error::DataStackUnderflow)
+    ));
+    assert_eq!(&[5], ctxt.data_stack().data());
+
+    ctxt.rt.unwind();
+    ctxt.evaluate(s("1 2 rot")).unwrap();
+    assert!(matches!(
+        ctxt.run_blocking(),
+        Err(Error::DataStackUnderflow)
+    ));
+    assert_eq!(&[1, 2], ctxt.data_stack().data());
+}
+
+#[test]
+fn check_stack() -> Result<(), Error> {
+    let mut ctxt = Context::with_builtins(std_builtins());
+    ctxt.check_stack = true;
+    ctxt.evaluate(s(": pair 2dup ;"))?;
+
+    ctxt.evaluate(s("5 pair"))?;
+    assert!(matches!(
+        ctxt.run_blocking(),
+        Err(Error::DataStackUnderflow)
+    ));
+    assert_eq!(ctxt.underflow_word(), Some("2dup"));
+    assert_eq!(ctxt.backtrace(), vec!["pair"]);
+    assert_eq!(&[5], ctxt.data_stack().data());
+    ctxt.rt.unwind();
+
+    assert_eq!(ctxt.with_stack_effect(&[5, 6], "pair")?, vec![5, 6, 5, 6]);
+    assert_eq!(ctxt.underflow_word(), None);
+
+    // Without the check, the builtin itself fails, with no word to report
+    ctxt.check_stack = false;
+    ctxt.evaluate(s("5 pair"))?;
+    assert!(matches!(
+        ctxt.run_blocking(),
+        Err(Error::DataStackUnderflow)
+    ));
+    assert_eq!(ctxt.underflow_word(), None);
+    Ok(())
+}
+
+#[test]
+fn compile_only_rejects_bare_lines() {
+    let mut ctxt = Context::with_builtins(std_builtins());
+    ctxt.compile_only = true;
+
+    ctxt.evaluate(s(": star 42 emit ;")).unwrap();
+    assert!(matches!(
+        ctxt.evaluate(s("star")),
+        Err(Error::NotADefinition)
+    ));
+
+    // Nothing was scheduled for execution
+    ctxt.run_blocking().unwrap();
+    assert_eq!("", &ctxt.output());
+    assert!(ctxt.dict.data.contains_key("star"));
+}
+
+#[test]
+fn pick_roll_out_of_depth() {
+    let cases: &[(&str, &[i32])] = &[
+        ("1 2 3 pick", &[1, 2, 3]),
+        ("1 2 3 roll", &[1, 2, 3]),
+        ("1 2 -1 pick", &[1, 2, -1]),
+        ("0 pick", &[0]),
+        ("0 roll", &[0]),
+        ("pick", &[]),
+    ];
+
+    for (case, remaining) in cases {
+        let mut ctxt = Context::with_builtins(std_builtins());
+        ctxt.evaluate(s(case)).unwrap();
+        assert!(matches!(
+            ctxt.run_blocking(),
+            Err(Error::DataStackUnderflow)
+        ));
+        assert_eq!(*remaining, ctxt.data_stack().data());
+    }
+}
+
+#[test]
+fn crlf_newlines() {
+    let mut ctxt = Context::with_builtins(std_builtins());
+    ctxt.rt.newline = Newline::CrLf;
+
+    ctxt.evaluate(s("42 emit cr 7 .")).unwrap();
+    ctxt.run_blocking().unwrap();
+    assert_eq!("*\r\n7\r\n", &ctxt.output());
+}
+
+#[test]
+fn word_deps() {
+    let mut ctxt = Context::with_builtins(std_builtins());
+
+    ctxt.evaluate(s(": star 42 emit ;")).unwrap();
+    ctxt.evaluate(s(": test star 42 emit star ;")).unwrap();
+
+    assert_eq!(vec!["star", "emit"], ctxt.word_deps("test"));
+    assert_eq!(vec!["emit"], ctxt.word_deps("star"));
+    assert!(ctxt.word_deps("nope").is_empty());
+}
+
+#[test]
+fn migrate_headerless() {
+    let mut ctxt = Context::with_builtins(std_builtins());
+    ctxt.evaluate(s(": star 42 emit ;")).unwrap();
+    let ser = ctxt.serialize();
+
+    // Version 0 files had no header
+    let mut legacy = rzcobs::encode(&postcard::to_stdvec(&ser).unwrap());
+    legacy.push(0);
+    assert_eq!(0, a4::file_version(&legacy));
+    assert!(matches!(
+        a4::decode(&legacy),
+        Err(Error::UnsupportedVersion)
+    ));
+
+    let migrated = a4::migrate(&legacy).unwrap();
+    assert_eq!(a4::VERSION, a4::file_version(&migrated));
+    assert_eq!(a4::encode(&ser), migrated);
+
+    let mut loaded = Context::with_builtins(std_builtins());
+    loaded.load_ser_dict(&a4::decode(&migrated).unwrap());
+    loaded.evaluate(s("star")).unwrap();
+    loaded.run_blocking().unwrap();
+    assert_eq!("*", &loaded.output());
+}
+
+#[test]
+fn loop_overflow() {
+    // The limit is not above the start, so the index overflows instead of wrapping
+    for (case, output) in [
+        ("-2147483648 2147483646 do 42 emit loop", "**"),
+        ("2147483646 2147483646 do 42 emit loop", "**"),
+        ("0 2147483647 do 42 emit loop", "*"),
+    ] {
+        let mut ctxt = Context::with_builtins(std_builtins());
+        ctxt.evaluate(s(case)).unwrap();
+        assert!(matches!(ctxt.run_blocking(), Err(Error::BadMath)));
+        assert_eq!(output, &ctxt.output());
+    }
+}
+
+#[test]
+fn hot_reload() {
+    let mut ctxt = Context::with_builtins(std_builtins());
+
+    ctxt.evaluate(s(": star 42 emit ;")).unwrap();
+    ctxt.evaluate(s(": stars star star ;")).unwrap();
+    ctxt.evaluate(s("7 stars")).unwrap();
+    ctxt.run_blocking().unwrap();
+    assert_eq!("**", &ctxt.output());
+
+    ctxt.hot_reload("star", "43 emit").unwrap();
+    ctxt.evaluate(s("stars")).unwrap();
+    ctxt.run_blocking().unwrap();
+    assert_eq!("++", &ctxt.output());
+    assert_eq!(&[7], ctxt.data_stack().data());
+
+    // Can't replace a word while it is running
+    ctxt.evaluate(s("star")).unwrap();
+    match ctxt.step().unwrap() {
+        StepResult::Working(WhichToken::Ref(rtw)) => {
+            let c = ctxt.resolve_seq(&rtw);
+            ctxt.rt.provide_seq_tok(c).unwrap();
+        }
+        _ => panic!("Expected to enter `star`"),
+    }
+    assert!(matches!(
+        ctxt.hot_reload("star", "44 emit"),
+        Err(Error::WordInUse)
+    ));
+    ctxt.run_blocking().unwrap();
+    assert_eq!("+", &ctxt.output());
+}
+
+#[test]
+fn retain_source() {
+    let mut ctxt = Context::with_builtins(std_builtins());
+    ctxt.evaluate(s(": star 42 emit ;")).unwrap();
+    assert_eq!(None, ctxt.dict.source("star"));
+
+    ctxt.dict.retain_source = true;
+    ctxt.evaluate(s(": Stars 2 0 DO star loop ;")).unwrap();
+    assert_eq!(
+        Some(s(": Stars 2 0 DO star loop ;").as_slice()),
+        ctxt.dict.source("stars")
+    );
+
+    ctxt.hot_reload("stars", "star star").unwrap();
+    assert_eq!(
+        Some(s(": stars star star ;").as_slice()),
+        ctxt.dict.source("stars")
+    );
+}
+
+#[test]
+fn custom_true_value() {
+    let mut ctxt = Context::with_builtins(std_builtins());
+    ctxt.rt.true_value = 1;
+
+    ctxt.evaluate(s("1 1 = . 1 2 < . 1 2 > . 2 2 = if 42 emit then"))
+        .unwrap();
+    ctxt.run_blocking().unwrap();
+    assert_eq!("1\n1\n0\n*", &ctxt.output());
+}
+
+#[test]
+fn step_events() {
+    let mut ctxt = Context::with_builtins(std_builtins());
+    ctxt.evaluate(s(": t 1 if 42 emit then ;")).unwrap();
+    ctxt.evaluate(s(": f 0 if 42 emit then ;")).unwrap();
+
+    let mut collect = |line: &str| {
+        ctxt.evaluate(s(line)).unwrap();
+        let mut events = Vec::new();
+        loop {
+            let event = ctxt.step_event().unwrap();
+            events.push(event.clone());
+            if event == StepEvent::Done {
+                break;
+            }
+        }
+        events
+    };
+
+    assert_eq!(
+        collect("t"),
+        vec![
+            StepEvent::EnteredSeq { name: "t".into() },
+            StepEvent::PushedLiteral { val: 1 },
+            StepEvent::PushedLiteral { val: 42 },
+            StepEvent::ExecutedBuiltin {
+                name: "emit".into()
+            },
+            StepEvent::Done,
+        ]
+    );
+
+    assert_eq!(
+        collect("f"),
+        vec![
+            StepEvent::EnteredSeq { name: "f".into() },
+            StepEvent::PushedLiteral { val: 0 },
+            StepEvent::Jumped { from: 1, to: 4 },
+            StepEvent::Done,
+        ]
+    );
+
+    assert_eq!(ctxt.output(), "*");
+}
+
+#[test]
+fn eval_expr() {
+    let mut ctxt = Context::with_builtins(std_builtins());
+    assert_eq!(ctxt.eval_expr("3 4 + 5 *").unwrap(), vec![35]);
+    assert!(matches!(
+        ctxt.eval_expr(": sq dup * ;"),
+        Err(Error::NotAnExpression)
+    ));
+    assert!(ctxt.dict.data.is_empty());
+}
+
+#[test]
+fn stray_colons_and_semicolons() {
+    let mut ctxt = Context::with_builtins(std_builtins());
+
+    let cases: &[(&str, &str)] = &[
+        ("42 emit ;", "UnexpectedSemicolon"),
+        (";", "UnexpectedSemicolon"),
+        (": foo 42 ; emit ;", "UnexpectedSemicolon"),
+        ("foo 42 emit ;", "UnexpectedSemicolon"),
+        (": foo 42 emit", "UnexpectedColon"),
+        ("42 : foo emit ;", "UnexpectedColon"),
+        (": foo : bar ;", "UnexpectedColon"),
+        (":", "MissingWordName"),
+        (": ;", "MissingWordName"),
+    ];
+
+    for (line, expected) in cases {
+        let err = ctxt.evaluate(s(line)).unwrap_err();
+        assert_eq!(&format!("{:?}", err), expected, "{:?}", line);
+    }
+
+    assert!(ctxt.dict.data.is_empty());
+    assert!(ctxt.flow_stack().is_empty());
+
+    // Even when split on every space, a string may hold a `;`
+    let split = r#"." a ; b""#.split_whitespace().map(str::to_string);
+    ctxt.evaluate(split.collect()).unwrap();
+    ctxt.run_blocking().unwrap();
+    assert_eq!(ctxt.output(), "a ; b");
+}
+
+#[test]
+fn push_pop_from_rust() {
+    let mut ctxt = Context::with_builtins(std_builtins());
+    ctxt.evaluate(s(": t r> r> + 2 * ;")).unwrap();
+
+    ctxt.rt.push_return(1).unwrap();
+    ctxt.rt.push_return(20).unwrap();
+    ctxt.evaluate(s("t")).unwrap();
+    ctxt.run_blocking().unwrap();
+
+    assert_eq!(ctxt.rt.pop_data().unwrap(), 42);
+    assert!(matches!(ctxt.rt.pop_data(), Err(Error::DataStackUnderflow)));
+
+    ctxt.rt.push_data(42).unwrap();
+    ctxt.evaluate(s(">r")).unwrap();
+    ctxt.run_blocking().unwrap();
+
+    assert_eq!(ctxt.rt.pop_return().unwrap(), 42);
+    assert!(ctxt.return_stack().is_empty());
+}
+
+#[test]
+fn rename_word() {
+    let mut ctxt = Context::with_builtins(std_builtins());
+    ctxt.dict.retain_source = true;
+    ctxt.evaluate(s(": star 42 emit ;")).unwrap();
+    ctxt.evaluate(s(": stars 0 do star loop ;")).unwrap();
+
+    ctxt.rename_word("star", "asterisk").unwrap();
+    assert!(ctxt.get_seq("star").is_none());
+    assert!(ctxt.word_deps("stars").contains(&"asterisk".to_string()));
+    assert!(!ctxt.word_deps("stars").contains(&"star".to_string()));
+    assert_eq!(
+        ctxt.dict.source("stars").unwrap().join(" "),
+        ": stars 0 do asterisk loop ;"
+    );
+    assert_eq!(
+        ctxt.dict.source("asterisk").unwrap().join(" "),
+        ": asterisk 42 emit ;"
+    );
+
+    ctxt.evaluate(s("3 stars asterisk")).unwrap();
+    ctxt.run_blocking().unwrap();
+    assert_eq!(ctxt.output(), "****");
+
+    assert!(matches!(
+        ctxt.rename_word("asterisk", "stars"),
+        Err(Error::WordExists)
+    ));
+    assert!(matches!(
+        ctxt.rename_word("asterisk", "emit"),
+        Err(Error::WordExists)
+    ));
+    assert!(matches!(
+        ctxt.rename_word("star", "splat"),
+        Err(Error::UnknownWord)
+    ));
+}
+
+#[test]
+fn reduce_empty_stack() {
+    let mut ctxt = Context::with_builtins(std_builtins());
+
+    for word in ["sum", "smax", "smin"] {
+        ctxt.evaluate(s(word)).unwrap();
+        assert!(matches!(ctxt.run_blocking(), Err(Error::DataStackEmpty)));
+        ctxt.rt.unwind();
+    }
+}
+
+#[test]
+fn clear_output() {
+    let mut ctxt = Context::with_builtins(std_builtins());
+
+    ctxt.evaluate(s("42 emit 42 emit")).unwrap();
+    ctxt.run_blocking().unwrap();
+    ctxt.rt.clear_output();
+
+    ctxt.evaluate(s("43 emit")).unwrap();
+    ctxt.run_blocking().unwrap();
+    assert_eq!(ctxt.output(), "+");
+}
+
+#[test]
+fn word_ids() {
+    assert_eq!(WordId::from("star"), WordId("star".to_string()));
+    assert_eq!(WordId::from("star".to_string()), WordId("star".to_string()));
+    assert_eq!(WordId::from(3usize).into_inner(), 3);
+    assert_eq!(VerbSeqInner::<usize>::from_word(3).tok, 3);
+    assert_eq!(VerbSeqInner::<String>::from_word("star").tok, "star");
+
+    let mut ctxt = Context::with_builtins(std_builtins());
+    ctxt.evaluate(s(": stars 0 do 42 emit loop ;")).unwrap();
+
+    assert!(ctxt.contains_word("stars"));
+    assert!(ctxt.contains_word(WordId::from("STARS")));
+    assert!(!ctxt.contains_word("emit"));
+
+    ctxt.rt.push_data(3).unwrap();
+    ctxt.call_word("stars").unwrap();
+    assert_eq!(ctxt.output(), "***");
+
+    assert!(matches!(ctxt.call_word("nope"), Err(Error::UnknownWord)));
+}
+
+#[test]
+fn garbage_never_panics() {
+    let mut ctxt = Context::with_builtins(std_builtins());
+    ctxt.evaluate(s(": star 42 emit ;")).unwrap();
+
+    let bad = [
+        ("bogusword", "UnknownWord"),
+        ("1 if 2", "MissingIfPair"),
+        ("1 if 2 else 3", "MissingElsePair"),
+        ("3 0 do star", "MissingLoopPair"),
+        ("begin star", "MissingUntilPair"),
+        ("0 until", "UnbalancedControl"),
+        ("begin 1 while star", "MissingRepeatPair"),
+        ("begin star repeat", "UnbalancedControl"),
+        ("1 while", "UnbalancedControl"),
+        ("then", "UnbalancedControl"),
+        ("star else", "UnbalancedControl"),
+        ("loop star", "UnbalancedControl"),
+        ("2 +loop", "UnbalancedControl"),
+        ("1 if leave then", "UnbalancedControl"),
+        ("1 case 1 of star endof", "MissingEndcasePair"),
+        ("1 case 1 of star", "MissingEndofPair"),
+        ("1 of star endof", "UnbalancedControl"),
+        ("3 0 do 1 if loop then", "UnbalancedControl"),
+        ("1 if else else then", "UnbalancedControl"),
+        ("( never closed", "UnterminatedComment"),
+        ("char ab emit", "BadCharLiteral"),
+        ("[char]", "BadCharLiteral"),
+        (".\" never closed", "UnterminatedString"),
+        ("variable", "MissingWordName"),
+        (": t 0 if star ;", "MissingIfPair"),
+        (": t do ;", "MissingLoopPair"),
+        (": t if ;", "MissingIfPair"),
+        (": t 1 if else ;", "MissingElsePair"),
+        (": t )) ;", "UnknownWord"),
+    ];
+
+    for (line, expected) in bad.iter() {
+        let err = ctxt.evaluate(s(line)).unwrap_err();
+        assert_eq!(&format!("{:?}", err), expected, "{:?}", line);
+    }
+
+    // A cheap and deterministic fuzzer: evaluating any sequence of these
+    // tokens must never panic, whether or not it compiles
+    let vocab = s(": ; if else then do loop ( ) 42 -1 star emit bogus \u{1F980} 0x10 99999999999");
+    let mut seed: u32 = 0x1234_5678;
+    for _ in 0..2000 {
+        let mut line = Vec::new();
+        seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+        for _ in 0..(seed >> 28) {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            line.push(vocab[(seed >> 16) as usize % vocab.len()].clone());
+        }
+        let _ = ctxt.evaluate(line);
+    }
+
+    // Failed definitions never modify the dictionary
+    assert!(ctxt.get_seq("t").is_none());
+}
+
+#[test]
+fn with_stack_effect() -> Result<(), Error> {
+    let mut ctxt = Context::with_builtins(std_builtins());
+    ctxt.evaluate(s(": sq dup * ;"))?;
+
+    assert_eq!(ctxt.with_stack_effect(&[3, 4], "+")?, vec![7]);
+    assert_eq!(ctxt.with_stack_effect(&[1, 2, 3], "rot")?, vec![2, 3, 1]);
+    assert_eq!(ctxt.with_stack_effect(&[5], "sq sq")?, vec![625]);
+    assert_eq!(ctxt.with_stack_effect(&[9], "drop")?, vec![]);
+    assert_eq!(ctxt.with_stack_effect(&[], "1 2")?, vec![1, 2]);
+
+    // The stack is always seeded from scratch, and left empty
+    ctxt.rt.push_data(100)?;
+    assert_eq!(ctxt.with_stack_effect(&[1], "dup")?, vec![1, 1]);
+    assert!(ctxt.data_stack().is_empty());
+
+    assert!(matches!(
+        ctxt.with_stack_effect(&[1], "+"),
+        Err(Error::DataStackUnderflow)
+    ));
+    assert!(ctxt.data_stack().is_empty());
+    Ok(())
+}
+
+#[test]
+fn column_table() -> Result<(), Error> {
+    let mut ctxt = Context::with_builtins(std_builtins());
+
+    // ( char stars -- ) print a row of stars, then the char in column 6
+    ctxt.evaluate(s(": row 0 do 42 emit loop 6 tab-to emit cr ;"))?;
+    ctxt.evaluate(s("49 1 row 50 3 row 51 5 row 52 7 row"))?;
+    ctxt.run_blocking()?;
+
+    assert_eq!(ctxt.output(), "*     1\n***   2\n***** 3\n*******4\n");
+    assert_eq!(ctxt.rt.column(), 0);
+
+    assert_eq!(ctxt.with_stack_effect(&[], "col")?, vec![0]);
+    assert_eq!(ctxt.with_stack_effect(&[], "3 spaces col")?, vec![3]);
+    assert_eq!(ctxt.with_stack_effect(&[], "42 emit col")?, vec![4]);
+    assert_eq!(ctxt.with_stack_effect(&[-2], "spaces col")?, vec![4]);
+    assert_eq!(ctxt.with_stack_effect(&[2], "tab-to col")?, vec![4]);
+    assert_eq!(ctxt.with_stack_effect(&[], "cr col")?, vec![0]);
+    assert_eq!(ctxt.output(), "   *\n");
+    Ok(())
+}
+
+#[test]
+fn abort_unwinds() -> Result<(), Error> {
+    let mut ctxt = Context::with_builtins(std_builtins());
+    ctxt.evaluate(s(": inner 1 if abort then 42 emit ;"))?;
+    ctxt.evaluate(s(": middle 7 >r 3 0 do inner loop r> ;"))?;
+    ctxt.evaluate(s(": outer 5 6 middle 42 emit ;"))?;
+
+    ctxt.evaluate(s("outer"))?;
+    assert!(matches!(ctxt.run_blocking(), Err(Error::Abort)));
+
+    assert!(ctxt.data_stack().is_empty());
+    assert!(ctxt.return_stack().is_empty());
+    assert!(ctxt.flow_stack().is_empty());
+    assert_eq!(ctxt.output(), "");
+
+    // Execution continues normally afterwards
+    ctxt.evaluate(s("42 emit"))?;
+    ctxt.run_blocking()?;
+    assert_eq!(ctxt.output(), "*");
+    Ok(())
+}
+
+#[test]
+fn environment_queries() -> Result<(), Error> {
+    let mut ctxt = Context::with_builtins(std_builtins());
+    assert_eq!(
+        ctxt.with_stack_effect(&[], "cell-bits max-int min-int")?,
+        vec![i32::BITS as i32, i32::MAX, i32::MIN]
+    );
+    Ok(())
+}
+
+#[test]
+fn dispatch_filter() -> Result<(), Error> {
+    let mut ctxt = Context::with_builtins(std_builtins());
+    ctxt.evaluate(s(": star 42 emit ;"))?;
+    ctxt.evaluate(s(": stars 0 do star loop ;"))?;
+
+    ctxt.dispatch_filter = Some(|name| match name {
+        "emit" => Dispatch::Deny,
+        _ => Dispatch::Allow,
+    });
+
+    ctxt.evaluate(s("1 2 3 stars"))?;
+    assert!(matches!(ctxt.run_blocking(), Err(Error::BuiltinDenied)));
+    assert_eq!(ctxt.output(), "");
+    assert!(ctxt.data_stack().is_empty());
+    assert!(ctxt.return_stack().is_empty());
+    assert!(ctxt.flow_stack().is_empty());
+
+    // Unfiltered builtins still work
+    assert_eq!(ctxt.with_stack_effect(&[3, 4], "+")?, vec![7]);
+
+    // Substitute `.` with a version that prints nothing
+    ctxt.dispatch_filter = Some(|name| match name {
+        "." => Dispatch::Substitute(a4_core::builtins::bi_drop),
+        _ => Dispatch::Allow,
+    });
+
+    ctxt.evaluate(s("1 . 2 star"))?;
+    ctxt.run_blocking()?;
+    assert_eq!(ctxt.output(), "*");
+    assert_eq!(ctxt.data_stack().data(), &[2]);
+    Ok(())
+}
+
+#[test]
+fn custom_definition_tokens() -> Result<(), Error> {
+    let mut ctxt = Context::with_builtins(std_builtins());
+    ctxt.def_start = "def".into();
+    ctxt.def_end = "end".into();
+    ctxt.dict.retain_source = true;
+
+    ctxt.evaluate(s("def star 42 emit end"))?;
+    ctxt.evaluate(s("DEF stars 0 do star loop END"))?;
+    ctxt.evaluate(s("3 stars"))?;
+    ctxt.run_blocking()?;
+    assert_eq!(ctxt.output(), "***");
+
+    ctxt.hot_reload("star", "43 emit")?;
+    let source = ctxt.dict.source("star").unwrap().join(" ");
+    assert_eq!(source, "def star 43 emit end");
+
+    // The defaults are no longer special
+    assert!(matches!(
+        ctxt.evaluate(s(": nope 42 emit ;")),
+        Err(Error::UnknownWord)
+    ));
+    assert!(matches!(
+        ctxt.evaluate(s("def oops star")),
+        Err(Error::UnexpectedColon)
+    ));
+    assert!(matches!(
+        ctxt.evaluate(s("star end")),
+        Err(Error::UnexpectedSemicolon)
+    ));
+    Ok(())
+}
+
+#[test]
+fn profiler_timing() -> Result<(), Error> {
+    fn nap(_: &mut StdRuntime) -> Result<(), Error> {
+        std::thread::sleep(Duration::from_millis(2));
+        Ok(())
+    }
+
+    fn doze(_: &mut StdRuntime) -> Result<(), Error> {
+        std::thread::sleep(Duration::from_millis(20));
+        Ok(())
+    }
+
+    let mut bis = std_builtins().to_vec();
+    bis.push(("nap", nap));
+    bis.push(("doze", doze));
+
+    let mut ctxt = Context::with_builtins(&bis);
+    ctxt.evaluate(s(": fast nap ;"))?;
+    ctxt.evaluate(s(": slow doze ;"))?;
+    ctxt.evaluate(s(": both fast fast slow ;"))?;
+
+    ctxt.profiler = Some(Profiler::new());
+    ctxt.evaluate(s("both"))?;
+    ctxt.run_blocking()?;
+
+    let prof = ctxt.profiler.take().unwrap();
+    let fast = prof.get("fast").unwrap();
+    let slow = prof.get("slow").unwrap();
+    assert_eq!(fast.calls, 2);
+    assert_eq!(slow.calls, 1);
+    assert_eq!(prof.get("nap").unwrap().calls, 2);
+    assert!(slow.elapsed > fast.elapsed);
+    assert!(prof.get("both").unwrap().elapsed >= slow.elapsed + fast.elapsed);
+    Ok(())
+}
+
+#[test]
+fn rdrop() -> Result<(), Error> {
+    let mut ctxt = Context::with_builtins(std_builtins());
+    ctxt.evaluate(s("1 2 >r rdrop"))?;
+    ctxt.run_blocking()?;
+    assert_eq!(ctxt.data_stack().data(), &[1]);
+    assert!(ctxt.return_stack().is_empty());
+
+    ctxt.evaluate(s("rdrop"))?;
+    assert!(ctxt.run_blocking().is_err());
+    Ok(())
+}
+
+#[test]
+fn to_dot() -> Result<(), Error> {
+    let mut ctxt = Context::with_builtins(std_builtins());
+    ctxt.evaluate(s(": star 42 emit ;"))?;
+    ctxt.evaluate(s(": stars 0 do star loop ;"))?;
+    ctxt.evaluate(s(": say\" star ;"))?;
+
+    let dot = ctxt.to_dot();
+    assert!(dot.starts_with("digraph words {\n"));
+    assert!(dot.contains("    \"stars\" -> \"star\";\n"));
+    assert!(dot.contains("    \"say\\\"\" -> \"star\";\n"));
+
+    // Builtins are not part of the graph
+    assert!(!dot.contains("emit"));
+    Ok(())
+}
+
+#[test]
+fn emit_modes() -> Result<(), Error> {
+    let mut ctxt = Context::with_builtins(std_builtins());
+
+    // 0xD841 is a surrogate, which is not a valid char
+    assert_eq!(ctxt.rt.emit_mode, EmitMode::Lossy);
+    ctxt.evaluate(s("55361 emit"))?;
+    ctxt.run_blocking()?;
+    assert_eq!(ctxt.output(), "‽");
+
+    ctxt.rt.emit_mode = EmitMode::Strict;
+    ctxt.evaluate(s("55361 emit"))?;
+    assert!(matches!(ctxt.run_blocking(), Err(Error::BadMath)));
+    ctxt.rt.unwind();
+    ctxt.evaluate(s("42 emit"))?;
+    ctxt.run_blocking()?;
+    assert_eq!(ctxt.output(), "*");
+
+    ctxt.rt.emit_mode = EmitMode::RawByte;
+    ctxt.evaluate(s("55361 emit 298 emit"))?;
+    ctxt.run_blocking()?;
+    assert_eq!(ctxt.output(), "A*");
+    Ok(())
+}
+
+#[test]
+fn quit_keeps_data() -> Result<(), Error> {
+    let mut ctxt = Context::with_builtins(std_builtins());
+    ctxt.evaluate(s(": inner 42 emit 3 >r quit 43 emit ;"))?;
+    ctxt.evaluate(s(": outer 1 inner 44 emit ;"))?;
+
+    ctxt.evaluate(s("2 outer 45 emit"))?;
+    ctxt.run_blocking()?;
+    assert_eq!(ctxt.output(), "*");
+    assert_eq!(ctxt.data_stack().data(), &[2, 1]);
+    assert!(ctxt.return_stack().is_empty());
+    assert!(ctxt.flow_stack().is_empty());
+
+    // The next run starts afresh, and runs to completion
+    ctxt.evaluate(s("+ ."))?;
+    ctxt.run_blocking()?;
+    assert_eq!(ctxt.output(), "3\n");
+
+    ctxt.evaluate(s("7 quit"))?;
+    assert_eq!(ctxt.step_event()?, StepEvent::PushedLiteral { val: 7 });
+    let name = "quit".to_string();
+    assert_eq!(ctxt.step_event()?, StepEvent::ExecutedBuiltin { name });
+    assert_eq!(ctxt.step_event()?, StepEvent::Quit);
+    assert_eq!(ctxt.step_event()?, StepEvent::Done);
+    Ok(())
+}
+
+#[test]
+fn try_call_hooks() -> Result<(), Error> {
+    let mut ctxt = Context::with_builtins(std_builtins());
+    ctxt.evaluate(s(": on-tick 42 emit ;"))?;
+
+    assert_eq!(ctxt.try_call("on-tick")?, Some("*".to_string()));
+    assert_eq!(ctxt.try_call("ON-TICK")?, Some("*".to_string()));
+    assert_eq!(ctxt.try_call("on-message")?, None);
+
+    // Builtins are not hooks
+    assert_eq!(ctxt.try_call("cr")?, None);
+    Ok(())
+}
+
+#[test]
+fn evaluate_lines() {
+    let mut ctxt = Context::with_builtins(std_builtins());
+    let script = ": star 42 emit ;\n\n3 0 do star loop\n: two 2 ;\ntwo two + .";
+    let lines = script.lines().map(str::to_string);
+    assert_eq!(ctxt.evaluate_lines(lines).unwrap(), "***4\n");
+    assert!(ctxt.contains_word("two"));
+    assert!(ctxt.data_stack().is_empty());
+
+    let lines = "star\nstar drop\nstar".lines().map(str::to_string);
+    let err = ctxt.evaluate_lines(lines).unwrap_err();
+    assert_eq!(err.line, 2);
+    assert!(matches!(err.error, Error::DataStackUnderflow));
+    assert_eq!(err.output, "**");
+}
+
+#[test]
+fn bit_position_range() -> Result<(), Error> {
+    let mut ctxt = Context::with_builtins(std_builtins());
+    let bits = ["1 32 bit-set", "1 -1 bit-clr", "1 99 bit-test"];
+    let shifts = ["1 32 lshift", "1 -1 rshift"];
+    for line in bits.iter().chain(shifts.iter()) {
+        ctxt.evaluate(s(line))?;
+        assert!(matches!(ctxt.run_blocking(), Err(Error::BadMath)));
+        ctxt.rt.unwind();
+    }
+
+    // A bad position leaves the stack as it was
+    for line in bits {
+        ctxt.evaluate(s(line))?;
+        assert!(matches!(ctxt.run_blocking(), Err(Error::BadMath)));
+        assert_eq!(ctxt.data_stack().data().len(), 2, "{}", line);
+        ctxt.rt.unwind();
+    }
+    Ok(())
+}
+
+#[test]
+fn builtin_arity() {
+    let ctxt = Context::with_builtins(std_builtins());
+    assert_eq!(ctxt.builtin_arity("+"), Some((2, 1)));
+    assert_eq!(ctxt.builtin_arity("dup"), Some((1, 2)));
+    assert_eq!(ctxt.builtin_arity("emit"), Some((1, 0)));
+    assert_eq!(ctxt.builtin_arity("rot"), Some((3, 3)));
+    assert_eq!(ctxt.builtin_arity("SWAP"), Some((2, 2)));
+
+    // Variable arity, and not a builtin
+    assert_eq!(ctxt.builtin_arity("pick"), None);
+    assert_eq!(ctxt.builtin_arity("roll"), None);
+    assert_eq!(ctxt.builtin_arity("star"), None);
+
+    // Only builtins that are registered have an arity
+    let only_mul: Vec<_> = std_builtins()
+        .iter()
+        .filter(|(name, _)| *name == "*")
+        .copied()
+        .collect();
+    let ctxt = Context::with_builtins(&only_mul);
+    assert_eq!(ctxt.builtin_arity("*"), Some((2, 1)));
+    assert_eq!(ctxt.builtin_arity("+"), None);
+}
+
+#[test]
+fn step_back() -> Result<(), Error> {
+    type State = (Vec<i32>, Vec<i32>, usize);
+    fn state(ctxt: &Context) -> State {
+        (
+            ctxt.data_stack().data().to_vec(),
+            ctxt.return_stack().data().to_vec(),
+            ctxt.flow_stack().data().len(),
+        )
+    }
+
+    let mut ctxt = Context::with_builtins(std_builtins());
+    ctxt.history_len = 4;
+    ctxt.evaluate(s(": star 42 emit ;"))?;
+    ctxt.evaluate(s("1 2 >r 3 + dup star"))?;
+
+    // Nothing to undo yet
+    assert!(!ctxt.step_back());
+
+    let mut seen = vec![state(&ctxt)];
+    for _ in 0..6 {
+        ctxt.step_event()?;
+        seen.push(state(&ctxt));
+    }
+    assert_eq!(seen[6], (vec![4, 4], vec![2], 1));
+
+    // Only the last four steps are kept
+    for expected in seen[2..6].iter().rev() {
+        assert!(ctxt.step_back());
+        assert_eq!(&state(&ctxt), expected);
+    }
+    assert!(!ctxt.step_back());
+
+    // Running forwards again repeats the same steps
+    ctxt.run_blocking()?;
+    assert_eq!(ctxt.output(), "*");
+    assert_eq!(ctxt.data_stack().data(), &[4, 4]);
+    Ok(())
+}
+
+#[test]
+fn resolve_seq() -> Result<(), Error> {
+    let mut ctxt = Context::with_builtins(std_builtins());
+    ctxt.evaluate(s(": star 42 emit ;"))?;
+
+    let step = |idx| VerbSeqInner {
+        tok: "star".to_string(),
+        idx,
+    };
+    let first = ctxt.resolve_seq(&step(0));
+    assert!(matches!(first, Some(RuntimeWord::LiteralVal(42))));
+    let second = ctxt.resolve_seq(&step(1));
+    assert!(matches!(second, Some(RuntimeWord::Verb(_))));
+    assert!(ctxt.resolve_seq(&step(2)).is_none());
+
+    let missing = VerbSeqInner::from_word("nope");
+    assert!(ctxt.resolve_seq(&missing).is_none());
+    Ok(())
+}
+
+#[test]
+fn backtrace() -> Result<(), Error> {
+    let mut ctxt = Context::with_builtins(std_builtins());
+    ctxt.evaluate(s(": inner drop drop ;"))?;
+    ctxt.evaluate(s(": middle 42 emit inner ;"))?;
+    ctxt.evaluate(s(": outer 1 middle ;"))?;
+    assert!(ctxt.backtrace().is_empty());
+
+    ctxt.evaluate(s("outer"))?;
+    let res = ctxt.run_blocking();
+    assert!(matches!(res, Err(Error::DataStackUnderflow)));
+    assert_eq!(ctxt.backtrace(), vec!["outer", "middle", "inner"]);
+    Ok(())
+}
+
+#[test]
+fn conditional_compilation() -> Result<(), Error> {
+    let source = "[if]\n: board 42 emit ;\n[else]\n: board 43 emit ;\n[then]\nboard";
+
+    for (flag, expected) in [(-1, "*"), (0, "+")] {
+        let mut ctxt = Context::with_builtins(std_builtins());
+        ctxt.rt.push_data(flag)?;
+        let output = ctxt.evaluate_lines(source.lines().map(str::to_string));
+        assert_eq!(output.unwrap(), expected);
+        assert!(ctxt.data_stack().is_empty());
+    }
+
+    // Nested regions within a skipped region are skipped entirely, and the
+    // flag can be computed by an earlier line
+    let mut ctxt = Context::with_builtins(std_builtins());
+    let source = "1 2 >\n[if] 1 [if] 42 emit [else] 43 emit [then] [else] 44 emit [then]";
+    let output = ctxt.evaluate_lines(source.lines().map(str::to_string));
+    assert_eq!(output.unwrap(), ",");
+    assert!(ctxt.data_stack().is_empty());
+
+    let err = ctxt.evaluate(s("[then]")).unwrap_err();
+    assert!(matches!(err, Error::UnbalancedControl));
+    Ok(())
+}
+
+#[test]
+fn stacks_mut_between_steps() -> Result<(), Error> {
+    let mut ctxt = Context::with_builtins(std_builtins());
+    ctxt.evaluate(s("40 r> + emit"))?;
+
+    assert_eq!(ctxt.step_event()?, StepEvent::PushedLiteral { val: 40 });
+    ctxt.rt.return_stack_mut().push(1)?;
+    let top = ctxt.rt.data_stack_mut().pop()?;
+    ctxt.rt.data_stack_mut().push(top + 1)?;
+
+    ctxt.run_blocking()?;
+    assert_eq!(ctxt.output(), "*");
+    assert!(ctxt.data_stack().is_empty());
+    assert!(ctxt.return_stack().is_empty());
+    Ok(())
+}
+
+#[test]
+fn compile_word() -> Result<(), Error> {
+    let mut ctxt = Context::with_builtins(std_builtins());
+    ctxt.evaluate(s(": existing 1 ;"))?;
+
+    let ser = ctxt.compile_word(": star 42 emit ;\n\n: stars 0 do star loop ;")?;
+    let names = vec!["star".to_string(), "stars".to_string()];
+    assert_eq!(ser.data_map, Some(names));
+    assert_eq!(ser.data.len(), 2);
+    assert_eq!(ser.data[0], vec![SerWord::LiteralVal(42), SerWord::Verb(0)]);
+    assert!(ser.data[1].contains(&SerWord::VerbSeq(0)));
+    assert_eq!(ser.bis[0], "emit");
+
+    // Nothing was defined, run, or printed
+    assert!(!ctxt.contains_word("star"));
+    assert!(ctxt.contains_word("existing"));
+    assert!(ctxt.flow_stack().is_empty());
+    assert_eq!(ctxt.output(), "");
+
+    // Only definitions are allowed, and existing words aren't visible
+    let err = ctxt.compile_word("42 emit").unwrap_err();
+    assert!(matches!(err, Error::NotADefinition));
+    let err = ctxt.compile_word(": two existing existing ;").unwrap_err();
+    assert!(matches!(err, Error::UnknownWord));
+    Ok(())
+}
+
+#[test]
+fn unloop_exit() -> Result<(), Error> {
+    let mut ctxt = Context::with_builtins(std_builtins());
+    let upto = ": upto 10 0 do 1 + dup 3 = if unloop exit then loop 42 emit ;";
+    ctxt.evaluate(s(upto))?;
+    ctxt.evaluate(s(": outer 0 upto 43 emit ;"))?;
+
+    ctxt.evaluate(s("outer"))?;
+    ctxt.run_blocking()?;
+    assert_eq!(ctxt.output(), "+");
+    assert_eq!(ctxt.data_stack().data(), &[3]);
+    assert!(ctxt.return_stack().is_empty());
+    assert!(ctxt.flow_stack().is_empty());
+
+    // Running to the end of the loop instead
+    ctxt.evaluate(s("drop 100 upto ."))?;
+    ctxt.run_blocking()?;
+    assert_eq!(ctxt.output(), "*110\n");
+    assert!(ctxt.return_stack().is_empty());
+    Ok(())
+}
+
+#[test]
+fn bad_number_literals() {
+    let mut ctxt = Context::with_builtins(std_builtins());
+    let lits =
+        "-0x80000001 0x100000000 0x -0b 0x-1 0x+1 0b102 --1 $zz $ % %2 $-1 _5 5_ -_5 1__0 0x_";
+    for lit in lits.split_whitespace() {
+        let err = ctxt.evaluate(vec![lit.to_string()]).unwrap_err();
+        assert!(matches!(err, Error::UnknownWord), "{}", lit);
+    }
+}
+
+#[test]
+fn normalize_flags() -> Result<(), Error> {
+    let mut ctxt = Context::with_builtins(std_builtins());
+    ctxt.rt.normalize_flags = true;
+
+    // Flags from builtins, and loops, are accepted with either true value
+    for true_value in [-1, 1] {
+        ctxt.rt.true_value = true_value;
+        let script = "1 2 < if 42 emit then 1 2 > if 43 emit then 3 0 do 44 emit loop";
+        ctxt.evaluate(s(script))?;
+        ctxt.run_blocking()?;
+        assert_eq!(ctxt.output(), "*,,,");
+    }
+
+    ctxt.evaluate(s("2 if 42 emit then"))?;
+    assert!(matches!(ctxt.run_blocking(), Err(Error::NonCanonicalFlag)));
+    assert!(ctxt.data_stack().is_empty());
+
+    ctxt.rt.normalize_flags = false;
+    ctxt.evaluate(s("2 if 42 emit then"))?;
+    ctxt.run_blocking()?;
+    assert_eq!(ctxt.output(), "*");
+    Ok(())
+}
+
+#[test]
+fn push_literals() -> Result<(), Error> {
+    let mut ctxt = Context::with_builtins(std_builtins());
+    ctxt.rt.push_literals(&[1, 2, 3])?;
+    assert_eq!(ctxt.data_stack().data(), &[1, 2, 3]);
+
+    ctxt.evaluate(s(". . ."))?;
+    ctxt.run_blocking()?;
+    assert_eq!(ctxt.output(), "3\n2\n1\n");
+    Ok(())
+}
+
+#[test]
+fn define_and_get() -> Result<(), Error> {
+    let mut ctxt = Context::with_builtins(std_builtins());
+    let name = ctxt.define_and_get(": STAR 42 emit ;")?;
+    assert_eq!(name, "star");
+
+    ctxt.call_word(name)?;
+    assert_eq!(ctxt.output(), "*");
+
+    let err = ctxt.define_and_get("42 emit").unwrap_err();
+    assert!(matches!(err, Error::NotADefinition));
+    assert!(ctxt.flow_stack().is_empty());
+
+    let err = ctxt.define_and_get(": ;").unwrap_err();
+    assert!(matches!(err, Error::MissingWordName));
+    Ok(())
+}
+
+#[test]
+fn stall_limit() -> Result<(), Error> {
+    let mut ctxt = Context::with_builtins(std_builtins());
+    ctxt.rt.stall_limit = Some(100);
+
+    // Resets the loop index every time around, so never finishes
+    ctxt.evaluate(s(": spin 2 0 do r> r> drop 0 >r >r loop ;"))?;
+    let err = ctxt.call_word("spin").unwrap_err();
+    assert!(matches!(err, Error::LikelyInfiniteLoop));
+    assert!(ctxt.flow_stack().is_empty());
+
+    // Many more iterations than the limit, but each one makes progress
+    ctxt.evaluate(s(": count 10000 0 do loop ;"))?;
+    ctxt.call_word("count")?;
+
+    ctxt.evaluate(s(": stars 1000 0 do 42 emit 0 drop loop ;"))?;
+    ctxt.call_word("stars")?;
+    assert_eq!(ctxt.output().len(), 1000);
+    Ok(())
+}
+
+#[test]
+fn warnings() {
+    let mut ctxt = Context::with_builtins(std_builtins());
+    let src = [": star 42 emit ;", "star", ": STAR 43 emit ;"];
+    let lines = src.iter().map(|l| l.to_string());
+    assert_eq!(ctxt.evaluate_lines(lines).unwrap(), "*");
+
+    let expected = Warning {
+        message: "redefined `star`".into(),
+        line: Some(3),
+    };
+    assert_eq!(ctxt.warnings(), [expected]);
+
+    ctxt.clear_warnings();
+    assert!(ctxt.warnings().is_empty());
+}
+
+#[test]
+fn need_input() -> Result<(), Error> {
+    let mut ctxt = Context::with_builtins(std_builtins());
+    ctxt.evaluate(s(": echo key emit key 1 + emit ;"))?;
+    ctxt.evaluate(s("echo"))?;
+
+    let mut canned = "hi".chars();
+    loop {
+        match ctxt.step()? {
+            StepResult::Done => break,
+            StepResult::NeedInput => ctxt.rt.provide_input(canned.next().unwrap() as i32)?,
+            StepResult::Working(WhichToken::Single(ft)) => ft.exec(&mut ctxt.rt)?,
+            StepResult::Working(WhichToken::Ref(rtw)) => {
+                let c = ctxt.resolve_seq(&rtw);
+                ctxt.rt.provide_seq_tok(c)?;
+            }
+            _ => unreachable!(),
+        }
+    }
+    assert_eq!(ctxt.output(), "hj");
+    assert!(canned.next().is_none());
+
+    // Input given ahead of time is used without yielding
+    ctxt.rt.provide_input('a' as i32)?;
+    ctxt.evaluate(s("key emit"))?;
+    ctxt.run_blocking()?;
+    assert_eq!(ctxt.output(), "a");
+
+    // A blocking run stops at the yield, and can be resumed
+    ctxt.evaluate(s("key emit"))?;
+    assert!(matches!(ctxt.run_blocking(), Err(Error::Input)));
+    ctxt.rt.provide_input('b' as i32)?;
+    ctxt.run_blocking()?;
+    assert_eq!(ctxt.output(), "b");
+    Ok(())
+}
+
+#[test]
+fn register_builtin() -> Result<(), Error> {
+    fn bi_double(rt: &mut StdRuntime) -> Result<(), Error> {
+        let val = rt.pop_data()?;
+        rt.push_data(val * 2)
+    }
+
+    let mut ctxt = Context::with_builtins(std_builtins());
+    assert!(ctxt.evaluate(s(": quad double double ;")).is_err());
+
+    ctxt.register_builtins(&[("double", bi_double)]);
+    ctxt.evaluate(s(": quad double double ;"))?;
+    assert_eq!(ctxt.eval_expr("3 quad")?, vec![12]);
+    assert!(ctxt.warnings().is_empty());
+
+    ctxt.register_builtin("dup", bi_double);
+    assert_eq!(ctxt.warnings()[0].message, "redefined builtin `dup`");
+    assert_eq!(ctxt.eval_expr("drop 3 dup")?, vec![6]);
+    Ok(())
+}
+
+#[test]
+fn divide_by_zero() -> Result<(), Error> {
+    let mut ctxt = Context::with_builtins(std_builtins());
+    for op in ["/", "mod", "/mod"] {
+        ctxt.evaluate(vec![":".into(), "by-zero".into(), op.into(), ";".into()])?;
+        ctxt.evaluate(s("7 0 by-zero"))?;
+        assert!(matches!(ctxt.run_blocking(), Err(Error::DivideByZero)));
+        assert!(ctxt.data_stack().is_empty());
+
+        // As with any failed builtin, the caller is left for inspection
+        assert_eq!(ctxt.backtrace(), vec!["by-zero"]);
+        ctxt.rt.unwind();
+    }
+
+    // Callers that don't leave the failure to be inspected unwind it
+    assert!(matches!(ctxt.eval_expr("7 0 /"), Err(Error::DivideByZero)));
+    assert!(ctxt.flow_stack().is_empty());
+    assert_eq!(ctxt.eval_expr("6 3 /")?, vec![2]);
+    Ok(())
+}
+
+#[test]
+fn unknown_word_is_reported() {
+    let mut ctxt = Context::with_builtins(std_builtins());
+    ctxt.evaluate(s(": star 42 emit ;")).unwrap();
+
+    let err = ctxt.evaluate(s("star bogusword")).unwrap_err();
+    assert!(matches!(err, Error::UnknownWord));
+    assert_eq!(ctxt.unknown_word(), Some("bogusword"));
+
+    // Found within control flow, and the failed definition is not stored
+    let err = ctxt
+        .evaluate(s(": t 1 if star else 3 0 do Nope loop then ;"))
+        .unwrap_err();
+    assert!(matches!(err, Error::UnknownWord));
+    assert_eq!(ctxt.unknown_word(), Some("nope"));
+    assert!(ctxt.get_seq("t").is_none());
+
+    ctxt.evaluate(s("star")).unwrap();
+    assert_eq!(ctxt.unknown_word(), None);
+}
+
+#[test]
+fn memory_bounds() -> Result<(), Error> {
+    let mut ctxt = Context::with_builtins(std_builtins());
+    ctxt.evaluate(s("variable x"))?;
+    assert_eq!(ctxt.eval_expr("x")?, vec![0]);
+
+    let lines = [
+        "1 1 !", "1 -1 !", "1 @", "-1 @", "1 1 +!", "1 2 0 2!", "0 2@",
+    ];
+    for line in lines {
+        ctxt.evaluate(s(line))?;
+        let res = ctxt.run_blocking();
+        assert!(matches!(res, Err(Error::BadAddress)), "{}", line);
+        ctxt.rt.unwind();
+    }
+
+    // Redefining a variable allocates a new cell, leaving the old one as is
+    ctxt.evaluate(s("variable x 9 x !"))?;
+    ctxt.run_blocking()?;
+    assert_eq!(ctxt.eval_expr("x x @ 0 @")?, vec![1, 9, 0]);
+    Ok(())
+}
+
+#[test]
+fn words_lists_dictionary() -> Result<(), Error> {
+    let mut ctxt = Context::with_builtins(std_builtins());
+    ctxt.evaluate(s(": star 42 emit ;"))?;
+    ctxt.evaluate(s(": list words ;"))?;
+    ctxt.evaluate(s("list"))?;
+    ctxt.run_blocking()?;
+
+    let out = ctxt.output();
+    let names: Vec<&str> = out.split_whitespace().collect();
+    assert_eq!(names, ctxt.words());
+    for name in ["+", "dup", "emit", "list", "star", "words"] {
+        assert!(names.contains(&name), "{}", name);
+    }
+    assert!(!out.contains("PRIV_"));
+
+    // Denying or replacing `words` prints nothing
+    ctxt.dispatch_filter = Some(|name| match name {
+        "words" => Dispatch::Deny,
+        _ => Dispatch::Allow,
+    });
+    ctxt.evaluate(s("words"))?;
+    assert!(matches!(ctxt.run_blocking(), Err(Error::BuiltinDenied)));
+    assert_eq!(ctxt.output(), "");
+
+    ctxt.dispatch_filter = Some(|name| match name {
+        "words" => Dispatch::Substitute(a4_core::builtins::bi_cr),
+        _ => Dispatch::Allow,
+    });
+    ctxt.evaluate(s("list"))?;
+    ctxt.run_blocking()?;
+    assert_eq!(ctxt.output(), "\n");
+
+    // As does a host builtin of the same name
+    ctxt.dispatch_filter = None;
+    ctxt.register_builtin("words", a4_core::builtins::bi_cr);
+    ctxt.evaluate(s(": list2 words ;"))?;
+    ctxt.evaluate(s("list2"))?;
+    ctxt.run_blocking()?;
+    assert_eq!(ctxt.output(), "\n");
+    Ok(())
+}
+
+#[test]
+fn forget_word() -> Result<(), Error> {
+    let mut ctxt = Context::with_builtins(std_builtins());
+    ctxt.evaluate(s(": star 42 emit ;"))?;
+    ctxt.evaluate(s(": junk 1 ;"))?;
+    ctxt.evaluate(s(": stars star star ;"))?;
+
+    // Words defined after the forgotten one go with it
+    ctxt.evaluate(s("forget JUNK"))?;
+    assert!(ctxt.contains_word("star"));
+    assert!(!ctxt.contains_word("junk"));
+    assert!(!ctxt.contains_word("stars"));
+    assert!(matches!(ctxt.evaluate(s("junk")), Err(Error::UnknownWord)));
+    assert!(matches!(
+        ctxt.evaluate(s("forget junk")),
+        Err(Error::UnknownWord)
+    ));
+    assert_eq!(ctxt.unknown_word(), Some("junk"));
+    assert!(matches!(
+        ctxt.evaluate(s("forget")),
+        Err(Error::MissingWordName)
+    ));
+
+    // Forgetting a redefined word brings back the definition it replaced,
+    // which earlier words go back to calling
+    ctxt.evaluate(s(": twice star star ;"))?;
+    ctxt.evaluate(s(": star 43 emit ;"))?;
+    ctxt.evaluate(s("twice"))?;
+    ctxt.run_blocking()?;
+    assert_eq!(ctxt.output(), "++");
+    ctxt.evaluate(s("forget star twice"))?;
+    ctxt.run_blocking()?;
+    assert_eq!(ctxt.output(), "**");
+    assert!(ctxt.contains_word("twice"));
+
+    // The rest of the line runs as usual
+    ctxt.evaluate(s("forget twice 42 emit"))?;
+    ctxt.run_blocking()?;
+    assert_eq!(ctxt.output(), "*");
+    assert!(!ctxt.contains_word("twice"));
+    assert!(ctxt.contains_word("star"));
+
+    // Memory allotted since the forgotten word is freed, but not before it
+    ctxt.evaluate(s("variable kept"))?;
+    let here = ctxt.rt.mem.len();
+    ctxt.evaluate(s("variable junk 10 allot"))?;
+    ctxt.run_blocking()?;
+    ctxt.evaluate(s(": more 1 ;"))?;
+    ctxt.evaluate(s("variable last"))?;
+    assert_eq!(ctxt.rt.mem.len(), here + 12);
+    ctxt.evaluate(s("forget junk"))?;
+    assert_eq!(ctxt.rt.mem.len(), here);
+    assert!(ctxt.contains_word("kept"));
+    assert!(!ctxt.contains_word("last"));
+    Ok(())
+}
+
 fn s(words: &str) -> Vec<String> {
-    words.split_whitespace().map(str::to_string).collect()
+    tokenize(words)
 }